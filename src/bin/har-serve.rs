@@ -0,0 +1,177 @@
+//! Serves the responses recorded in a HAR file over HTTP.
+//!
+//! usage: har-serve [--addr ADDR] [--fallback-status CODE] [--fallback-body TEXT]
+//!                  [--latency FACTOR] [--mode MODE --upstream ADDR] [MATCHING OPTIONS] FILE
+
+extern crate har;
+
+use std::env;
+use std::net::TcpListener;
+use std::process;
+
+use har::cassette::{Cassette, Mode};
+use har::matcher::Matcher;
+use har::serve::MockServer;
+use har::{Content, Response};
+
+const USAGE: &str = "\
+usage: har-serve [--addr ADDR] [--fallback-status CODE] [--fallback-body TEXT]
+                 [--latency FACTOR] [--mode MODE --upstream ADDR] [MATCHING OPTIONS] FILE
+
+    --latency FACTOR                replay recorded wait and receive times, scaled by FACTOR
+                                    (1 as recorded, 0.5 twice as fast, 2 twice as slow)
+
+cassette modes:
+    replay-only                     only serve recorded responses (the default)
+    record-missing                  forward unmatched requests to the upstream and record them
+    re-record-all                   forward every request and replace the recorded entries
+
+matching options:
+    --ignore-query-order            accept query parameters in any order
+    --ignore-query-param NAME       leave a query parameter out of matching
+    --match-headers                 also match request headers
+    --ignore-header NAME            leave a header out of matching
+    --match-cookies                 also match request cookies
+    --ignore-cookie NAME            leave a cookie out of matching
+    --ignore-body                   do not match request bodies
+    --rewrite-url REGEX REPLACEMENT rewrite URLs before matching them";
+
+struct Args {
+    file: String,
+    addr: String,
+    fallback_status: i32,
+    fallback_body: Option<String>,
+    latency: Option<f64>,
+    mode: Mode,
+    upstream: Option<String>,
+    matcher: Matcher,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = env::args().skip(1);
+    let mut file = None;
+    let mut addr = "127.0.0.1:8080".to_string();
+    let mut fallback_status = 404;
+    let mut fallback_body = None;
+    let mut latency = None;
+    let mut mode = Mode::ReplayOnly;
+    let mut upstream = None;
+    let mut matcher = Matcher::new();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("{} needs a value", flag));
+        match arg.as_str() {
+            "--addr" => addr = value("--addr")?,
+            "--fallback-status" => {
+                fallback_status = value("--fallback-status")?
+                    .parse()
+                    .map_err(|_| "--fallback-status needs a number".to_string())?
+            }
+            "--fallback-body" => fallback_body = Some(value("--fallback-body")?),
+            "--latency" => {
+                latency = value("--latency")?
+                    .parse()
+                    .ok()
+                    .filter(|factor: &f64| factor.is_finite() && *factor >= 0.0);
+                if latency.is_none() {
+                    return Err("--latency needs a non-negative number".to_string());
+                }
+            }
+            "--mode" => mode = value("--mode")?.parse()?,
+            "--upstream" => upstream = Some(value("--upstream")?),
+            "--ignore-query-order" => matcher = matcher.ignore_query_order(true),
+            "--ignore-query-param" => {
+                matcher = matcher.ignore_query_param(&value("--ignore-query-param")?)
+            }
+            "--match-headers" => matcher = matcher.match_headers(true),
+            "--ignore-header" => matcher = matcher.ignore_header(&value("--ignore-header")?),
+            "--match-cookies" => matcher = matcher.match_cookies(true),
+            "--ignore-cookie" => matcher = matcher.ignore_cookie(&value("--ignore-cookie")?),
+            "--ignore-body" => matcher = matcher.match_body(false),
+            "--rewrite-url" => {
+                let pattern = value("--rewrite-url")?;
+                let replacement = value("--rewrite-url")?;
+                matcher = matcher.rewrite_url(&pattern, &replacement)
+                    .map_err(|err| format!("invalid --rewrite-url pattern: {}", err))?
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if file.is_none() && !arg.starts_with("--") => file = Some(arg),
+            _ => return Err(format!("unexpected argument \"{}\"\n{}", arg, USAGE)),
+        }
+    }
+    Ok(Args {
+        file: file.ok_or(USAGE.to_string())?,
+        addr,
+        fallback_status,
+        fallback_body,
+        latency,
+        mode,
+        upstream,
+        matcher,
+    })
+}
+
+fn reason_phrase(status: i32) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        410 => "Gone",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+fn fallback(status: i32, body: Option<String>) -> Response {
+    let size = body.as_ref().map_or(0, |body| body.len() as i32);
+    Response::new(
+        status,
+        reason_phrase(status).to_string(),
+        "HTTP/1.1".to_string(),
+        Vec::new(),
+        Vec::new(),
+        Content::new(size, None, "text/plain".to_string(), body, None, None),
+        "".to_string(),
+        None,
+        None,
+        None
+    )
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let Args { file, addr, fallback_status, fallback_body, latency, mode, upstream, matcher } =
+        args;
+    let fallback = fallback(fallback_status, fallback_body);
+    let server = match (mode, upstream) {
+        (Mode::ReplayOnly, _) => {
+            let log = har::file::from_path(&file)
+                .map_err(|err| format!("cannot read {}: {}", file, err))?;
+            MockServer::new(log, fallback)
+        }
+        (mode, Some(upstream)) => {
+            let cassette = Cassette::new(file.clone(), mode, upstream);
+            MockServer::from_cassette(cassette, fallback)
+                .map_err(|err| format!("cannot read {}: {}", file, err))?
+        }
+        (mode, None) => return Err(format!("--mode {} needs --upstream", mode)),
+    };
+    let server = match latency {
+        Some(factor) => server.with_latency(factor).map_err(|err| err.to_string())?,
+        None => server,
+    };
+    let listener = TcpListener::bind(&addr)
+        .map_err(|err| format!("cannot listen on {}: {}", addr, err))?;
+    let addr = listener.local_addr().map_err(|err| err.to_string())?;
+    eprintln!("Serving {} ({}) on http://{}", file, mode, addr);
+    server.with_matcher(matcher).serve(listener).map_err(|err| err.to_string())
+}
+
+fn main() {
+    let result = parse_args().and_then(run);
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
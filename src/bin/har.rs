@@ -0,0 +1,524 @@
+//! Inspects and transforms HAR files.
+//!
+//! usage: har COMMAND [OPTIONS] [FILE]
+//!
+//! Commands read FILE, or standard input when it is missing or "-", and write to standard output
+//! unless given `-o PATH`. Gzip and zstd compressed input is recognized automatically, and
+//! output paths ending in `.gz` or `.zst` are compressed to match.
+
+extern crate har;
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+
+use har::detect::{Detector, Kind};
+use har::filter::Filter;
+use har::pseudonymize::Pseudonymizer;
+use har::redact::Rules;
+use har::waterfall::Waterfall;
+use har::{convert, diff, file, jar, merge, report, sidecar, split, validate, Log};
+
+const USAGE: &str = "\
+usage: har COMMAND [OPTIONS] [FILE]
+
+commands:
+    validate                        check FILE against the HAR spec
+    stats                           summarize requests, bytes, caching and timings
+    filter FILTERS                  keep the entries matching every filter
+    redact [RULES]                  scrub credentials, or replace them with pseudonyms
+    scan [--ignore KIND]            report credentials and personal data; fails if any are found
+    cookies [--all]                 replay Set-Cookie headers; fails if a request sent other cookies
+    merge [--sort] FILE...          combine logs, optionally ordering entries by start time
+    split (--by page|host | --window SECONDS) [--out-dir DIR]
+                                    write one log per page, host or time window
+    diff OLD NEW                    compare two logs; fails if they differ
+    convert --to har|jsonl|csv      write the log in another format
+    pretty                          rewrite the log in the canonical layout
+    extract --to DIR|ZIP            move bodies into files named by their SHA-256 hash
+    inline --from DIR|ZIP           move extracted bodies back into the log, checking hashes
+    dedup [--expand]                store repeated response bodies once, or embed them again
+    waterfall [--svg] [--width N] [--page ID]
+                                    draw the request timeline
+    report [--title TITLE]          write a standalone HTML report
+
+Unless noted, commands read FILE (standard input if missing or \"-\") and write to standard
+output, or to the path given with -o PATH. Gzip and zstd compressed input is recognized, and
+logs written to a path ending in .gz or .zst are compressed.
+
+filters:
+    --host GLOB, --path GLOB, --method METHOD, --status CODE, --status-class DIGIT,
+    --mime-type GLOB, --min-size BYTES, --max-size BYTES, --page ID, --has-header NAME,
+    --body-contains TEXT
+
+redaction rules (on top of the defaults):
+    --redact-header NAME, --keep-header NAME, --redact-param NAME, --keep-param NAME,
+    --redact-json-path PATH, --keep-cookies, --keep-server-ip
+    --pseudonymize KEYFILE          replace values with pseudonyms keyed by the file's contents
+
+finding kinds for scan --ignore:
+    aws-key, jwt, private-key, email, card-number, high-entropy, unscanned";
+
+/// The remaining command line arguments.
+struct Args {
+    args: env::Args,
+}
+
+impl Args {
+    fn next(&mut self) -> Option<String> {
+        self.args.next()
+    }
+
+    fn value(&mut self, flag: &str) -> Result<String, String> {
+        self.args.next().ok_or(format!("{} needs a value", flag))
+    }
+
+    fn number<T: FromStr>(&mut self, flag: &str) -> Result<T, String> {
+        self.value(flag)?.parse().map_err(|_| format!("{} needs a number", flag))
+    }
+}
+
+/// The input and output options shared by all commands.
+#[derive(Default)]
+struct Io {
+    inputs: Vec<String>,
+    output: Option<String>,
+}
+
+impl Io {
+    /// Takes `arg` as an input or output option, or fails if it is neither.
+    fn parse(&mut self, arg: String, args: &mut Args) -> Result<(), String> {
+        match arg.as_str() {
+            "-o" | "--output" => self.output = Some(args.value(&arg)?),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            "-" => self.inputs.push(arg),
+            _ if !arg.starts_with('-') => self.inputs.push(arg),
+            _ => return Err(format!("unexpected argument \"{}\"\n{}", arg, USAGE)),
+        }
+        Ok(())
+    }
+
+    /// The single input, or standard input if none was given.
+    fn input(&self) -> Result<Option<&str>, String> {
+        match self.inputs.len() {
+            0 => Ok(None),
+            1 => Ok(Some(&self.inputs[0])),
+            _ => Err(format!("expected one input file\n{}", USAGE)),
+        }
+    }
+
+    fn read(&self) -> Result<Log, String> {
+        read_log(self.input()?)
+    }
+
+    fn write(&self, log: &Log) -> Result<(), String> {
+        match self.output.as_deref() {
+            None | Some("-") => {
+                let stdout = io::stdout();
+                file::to_writer(stdout.lock(), log).map_err(|err| err.to_string())
+            }
+            Some(path) => {
+                file::to_path(path, log).map_err(|err| format!("cannot write {}: {}", path, err))
+            }
+        }
+    }
+
+    fn write_text(&self, text: &str) -> Result<(), String> {
+        self.write_with(|writer| writer.write_all(text.as_bytes()))
+    }
+
+    fn write_with<F>(&self, write: F) -> Result<(), String>
+        where F: FnOnce(&mut dyn Write) -> io::Result<()>
+    {
+        let result = match self.output.as_deref() {
+            None | Some("-") => {
+                let stdout = io::stdout();
+                let mut stdout = stdout.lock();
+                write(&mut stdout).and_then(|_| stdout.flush())
+            }
+            Some(path) => fs::File::create(path).and_then(|mut file| write(&mut file)),
+        };
+        result.map_err(|err| format!("cannot write output: {}", err))
+    }
+}
+
+/// Reads the log at `path`, or from standard input if `path` is `None` or "-".
+fn read_log(path: Option<&str>) -> Result<Log, String> {
+    let name = path.unwrap_or("-");
+    let mut bytes = Vec::new();
+    let read = match path {
+        None | Some("-") => io::stdin().read_to_end(&mut bytes),
+        Some(path) => fs::File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)),
+    };
+    read.map_err(|err| format!("cannot read {}: {}", name, err))?;
+    file::from_reader(&bytes[..]).map_err(|err| format!("cannot read {}: {}", name, err))
+}
+
+fn validate(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    while let Some(arg) = args.next() {
+        io.parse(arg, &mut args)?;
+    }
+    let problems = validate::validate(&io.read()?);
+    let report: String = problems.iter().map(|problem| format!("{}\n", problem)).collect();
+    io.write_text(&report)?;
+    Ok(if problems.is_empty() { 0 } else { 1 })
+}
+
+fn stats(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    while let Some(arg) = args.next() {
+        io.parse(arg, &mut args)?;
+    }
+    io.write_text(&io.read()?.stats().to_string())?;
+    Ok(0)
+}
+
+fn filter(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut filter = Filter::any();
+    while let Some(arg) = args.next() {
+        let condition = match arg.as_str() {
+            "--host" => Filter::host(&args.value(&arg)?),
+            "--path" => Filter::path(&args.value(&arg)?),
+            "--method" => Filter::method(&args.value(&arg)?),
+            "--status" => Filter::status(args.number(&arg)?),
+            "--status-class" => Filter::status_class(args.number(&arg)?),
+            "--mime-type" => Filter::mime_type(&args.value(&arg)?),
+            "--min-size" => Filter::min_size(args.number(&arg)?),
+            "--max-size" => Filter::max_size(args.number(&arg)?),
+            "--page" => Filter::page(&args.value(&arg)?),
+            "--has-header" => Filter::has_header(&args.value(&arg)?),
+            "--body-contains" => Filter::body_contains(&args.value(&arg)?),
+            _ => {
+                io.parse(arg, &mut args)?;
+                continue;
+            }
+        };
+        filter = filter.and(condition);
+    }
+    io.write(&io.read()?.filtered(&filter))?;
+    Ok(0)
+}
+
+fn redact(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut rules = Rules::new();
+    let mut key_file = None;
+    while let Some(arg) = args.next() {
+        rules = match arg.as_str() {
+            "--redact-header" => rules.redact_header(&args.value(&arg)?),
+            "--keep-header" => rules.keep_header(&args.value(&arg)?),
+            "--redact-param" => rules.redact_param(&args.value(&arg)?),
+            "--keep-param" => rules.keep_param(&args.value(&arg)?),
+            "--redact-json-path" => {
+                rules.redact_json_path(&args.value(&arg)?).map_err(|err| err.to_string())?
+            }
+            "--keep-cookies" => rules.redact_cookies(false),
+            "--keep-server-ip" => rules.redact_server_ip_address(false),
+            "--pseudonymize" => {
+                key_file = Some(args.value(&arg)?);
+                rules
+            }
+            _ => {
+                io.parse(arg, &mut args)?;
+                rules
+            }
+        };
+    }
+    let mut log = io.read()?;
+    match key_file {
+        Some(path) => {
+            let key = fs::read(&path).map_err(|err| format!("cannot read {}: {}", path, err))?;
+            log.pseudonymize(&rules, &Pseudonymizer::new(&key));
+        }
+        None => log.redact(&rules),
+    }
+    io.write(&log)?;
+    Ok(0)
+}
+
+fn scan(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut detector = Detector::new();
+    while let Some(arg) = args.next() {
+        if arg == "--ignore" {
+            let kind = match args.value(&arg)?.as_str() {
+                "aws-key" => Kind::AwsKey,
+                "jwt" => Kind::Jwt,
+                "private-key" => Kind::PrivateKey,
+                "email" => Kind::Email,
+                "card-number" => Kind::CardNumber,
+                "high-entropy" => Kind::HighEntropy,
+                "unscanned" => Kind::Unscanned,
+                other => return Err(format!("unknown finding kind \"{}\"", other)),
+            };
+            detector = detector.ignore(kind);
+        } else {
+            io.parse(arg, &mut args)?;
+        }
+    }
+    let findings = detector.scan(&io.read()?);
+    let report: String = findings.iter().map(|finding| format!("{}\n", finding)).collect();
+    io.write_text(&report)?;
+    Ok(if findings.is_empty() { 0 } else { 1 })
+}
+
+fn cookies(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut all = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--all" => all = true,
+            _ => io.parse(arg, &mut args)?,
+        }
+    }
+    let steps = jar::replay(&io.read()?);
+    let report: String = steps.iter()
+        .filter(|step| all || !step.matches())
+        .map(|step| format!("{}\n", step))
+        .collect();
+    io.write_text(&report)?;
+    Ok(if steps.iter().all(|step| step.matches()) { 0 } else { 1 })
+}
+
+fn merge(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut sort = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sort" => sort = true,
+            _ => io.parse(arg, &mut args)?,
+        }
+    }
+    if io.inputs.is_empty() {
+        return Err(format!("merge needs the files to merge\n{}", USAGE));
+    }
+    let mut logs = Vec::new();
+    for input in &io.inputs {
+        let name = Path::new(input).file_stem().map_or(input.clone(), |stem| {
+            stem.to_string_lossy().trim_end_matches(".har").to_string()
+        });
+        logs.push((name, read_log(Some(input))?));
+    }
+    io.write(&merge::merge(logs, sort))?;
+    Ok(0)
+}
+
+fn split(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut by = None;
+    let mut window = None;
+    let mut out_dir = ".".to_string();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--by" => by = Some(args.value(&arg)?),
+            "--window" => window = Some(Duration::from_secs(args.number(&arg)?)),
+            "--out-dir" => out_dir = args.value(&arg)?,
+            _ => io.parse(arg, &mut args)?,
+        }
+    }
+    let log = io.read()?;
+    let parts = match (by.as_deref(), window) {
+        (Some("page"), None) => split::by_page(&log),
+        (Some("host"), None) => split::by_host(&log),
+        (None, Some(window)) => {
+            split::by_time_window(&log, window).map_err(|err| err.to_string())?
+        }
+        _ => return Err(format!("split needs one of --by page, --by host or --window\n{}",
+                                USAGE)),
+    };
+    fs::create_dir_all(&out_dir).map_err(|err| format!("cannot create {}: {}", out_dir, err))?;
+    for (name, part) in parts {
+        let name: String = name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+            .collect();
+        let path = Path::new(&out_dir).join(format!("{}.har", name));
+        file::to_path(&path, &part)
+            .map_err(|err| format!("cannot write {}: {}", path.display(), err))?;
+        eprintln!("{}", path.display());
+    }
+    Ok(0)
+}
+
+fn diff(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    while let Some(arg) = args.next() {
+        io.parse(arg, &mut args)?;
+    }
+    if io.inputs.len() != 2 {
+        return Err(format!("diff needs two files\n{}", USAGE));
+    }
+    let old = read_log(Some(&io.inputs[0]))?;
+    let new = read_log(Some(&io.inputs[1]))?;
+    let diff = diff::diff(&old, &new);
+    io.write_text(&diff.to_string())?;
+    Ok(if diff.is_empty() { 0 } else { 1 })
+}
+
+fn convert(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut format = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => format = Some(args.value(&arg)?),
+            _ => io.parse(arg, &mut args)?,
+        }
+    }
+    let log = io.read()?;
+    match format.as_deref() {
+        Some("har") => io.write(&log)?,
+        Some("jsonl") => {
+            io.write_with(|writer| {
+                convert::to_jsonl(writer, &log).map_err(|err| io::Error::other(err.to_string()))
+            })?
+        }
+        Some("csv") => io.write_with(|writer| convert::to_csv(writer, &log))?,
+        Some(other) => return Err(format!("unknown format \"{}\"", other)),
+        None => return Err(format!("convert needs --to FORMAT\n{}", USAGE)),
+    }
+    Ok(0)
+}
+
+fn pretty(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    while let Some(arg) = args.next() {
+        io.parse(arg, &mut args)?;
+    }
+    io.write(&io.read()?)?;
+    Ok(0)
+}
+
+fn extract(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut sidecar = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => sidecar = Some(args.value("--to")?),
+            _ => io.parse(arg, &mut args)?,
+        }
+    }
+    let sidecar = sidecar.ok_or(format!("extract needs --to DIR|ZIP\n{}", USAGE))?;
+    let mut log = io.read()?;
+    sidecar::extract(&mut log, &sidecar)
+        .map_err(|err| format!("cannot write {}: {}", sidecar, err))?;
+    io.write(&log)?;
+    Ok(0)
+}
+
+fn inline(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut sidecar = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => sidecar = Some(args.value("--from")?),
+            _ => io.parse(arg, &mut args)?,
+        }
+    }
+    let sidecar = sidecar.ok_or(format!("inline needs --from DIR|ZIP\n{}", USAGE))?;
+    let mut log = io.read()?;
+    sidecar::inline(&mut log, &sidecar)
+        .map_err(|err| format!("cannot read {}: {}", sidecar, err))?;
+    io.write(&log)?;
+    Ok(0)
+}
+
+fn dedup(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut expand = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--expand" => expand = true,
+            _ => io.parse(arg, &mut args)?,
+        }
+    }
+    let mut log = io.read()?;
+    if expand {
+        log.expand_bodies();
+    } else {
+        log.dedup_bodies();
+    }
+    io.write(&log)?;
+    Ok(0)
+}
+
+fn waterfall(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut svg = false;
+    let mut width = 80;
+    let mut page = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--svg" => svg = true,
+            "--width" => width = args.number(&arg)?,
+            "--page" => page = Some(args.value(&arg)?),
+            _ => io.parse(arg, &mut args)?,
+        }
+    }
+    let log = io.read()?;
+    let waterfall = match page {
+        Some(id) => {
+            Waterfall::from_page(&log, &id).ok_or(format!("no page has the id \"{}\"", id))?
+        }
+        None => Waterfall::from_log(&log),
+    };
+    io.write_text(&if svg { waterfall.to_svg() } else { waterfall.to_ascii(width) })?;
+    Ok(0)
+}
+
+fn report(mut args: Args) -> Result<i32, String> {
+    let mut io = Io::default();
+    let mut title = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--title" => title = Some(args.value(&arg)?),
+            _ => io.parse(arg, &mut args)?,
+        }
+    }
+    let title = title.unwrap_or_else(|| io.inputs.first().cloned().unwrap_or("HAR".to_string()));
+    io.write_text(&report::html(&io.read()?, &title))?;
+    Ok(0)
+}
+
+fn run() -> Result<i32, String> {
+    let mut args = Args { args: env::args() };
+    args.next();
+    let command = args.next().ok_or(USAGE.to_string())?;
+    match command.as_str() {
+        "validate" => validate(args),
+        "stats" => stats(args),
+        "filter" => filter(args),
+        "redact" => redact(args),
+        "scan" => scan(args),
+        "cookies" => cookies(args),
+        "merge" => merge(args),
+        "split" => split(args),
+        "diff" => diff(args),
+        "convert" => convert(args),
+        "pretty" => pretty(args),
+        "extract" => extract(args),
+        "inline" => inline(args),
+        "dedup" => dedup(args),
+        "waterfall" => waterfall(args),
+        "report" => report(args),
+        "-h" | "--help" | "help" => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        _ => Err(format!("unknown command \"{}\"\n{}", command, USAGE)),
+    }
+}
+
+fn main() {
+    match run() {
+        Ok(code) => process::exit(code),
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Access to the raw bytes of recorded bodies.
+
+use std::error;
+use std::fmt;
+
+use base64;
+use base64::Engine;
+
+use Content;
+
+/// The reason a recorded body could not be turned back into bytes.
+#[derive(Debug)]
+pub enum BodyError {
+    /// `content.encoding` names an encoding this crate does not understand.
+    UnknownEncoding(String),
+
+    /// `content.encoding` is "base64" but the text is not valid base64.
+    Base64(base64::DecodeError),
+
+    /// `content._body` names a shared body that is not in the log.
+    UnknownBody(String),
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BodyError::UnknownEncoding(ref encoding) => {
+                write!(f, "unknown content encoding \"{}\"", encoding)
+            }
+            BodyError::Base64(ref err) => write!(f, "invalid base64 content: {}", err),
+            BodyError::UnknownBody(ref hash) => write!(f, "no shared body \"{}\" in the log", hash),
+        }
+    }
+}
+
+impl error::Error for BodyError {}
+
+impl Content {
+    /// Returns the response body as the bytes the browser operated on.
+    ///
+    /// Base64 encoded text is decoded; plain text is returned as its UTF-8 bytes. A missing text
+    /// field, as left by `sidecar::extract`, yields an empty body. A body shared with other
+    /// entries by `Log::dedup_bodies` is read from the shared copy.
+    pub fn body_bytes(&self) -> Result<Vec<u8>, BodyError> {
+        let (text, encoding) = match (&self.text, &self.shared, &self.body) {
+            (Some(text), _, _) => (text, &self.encoding),
+            (None, Some(shared), _) => (&shared.text, &shared.encoding),
+            (None, None, Some(hash)) => return Err(BodyError::UnknownBody(hash.clone())),
+            (None, None, None) => return Ok(Vec::new()),
+        };
+        match *encoding {
+            None => Ok(text.as_bytes().to_vec()),
+            Some(ref encoding) if encoding.eq_ignore_ascii_case("base64") => {
+                base64::engine::general_purpose::STANDARD
+                    .decode(text.as_bytes())
+                    .map_err(BodyError::Base64)
+            }
+            Some(ref encoding) => Err(BodyError::UnknownEncoding(encoding.clone())),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use Content;
+
+    #[test]
+    fn test_body_bytes_plain() {
+        let content = Content::new(5, None, "text/plain".to_string(),
+                                   Some("hello".to_string()), None, None);
+        assert_eq!(content.body_bytes().unwrap(), b"hello".to_vec());
+    }
+
+    #[test]
+    fn test_body_bytes_base64() {
+        let content = Content::new(3, None, "application/octet-stream".to_string(),
+                                   Some("AP8K".to_string()), Some("base64".to_string()), None);
+        assert_eq!(content.body_bytes().unwrap(), vec![0x00, 0xff, 0x0a]);
+    }
+
+    #[test]
+    fn test_body_bytes_no_text() {
+        let content = Content::new(0, None, "text/plain".to_string(), None, None, None);
+        assert!(content.body_bytes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_body_bytes_unknown_encoding() {
+        let content = Content::new(5, None, "text/plain".to_string(),
+                                   Some("hello".to_string()), Some("rot13".to_string()), None);
+        assert!(content.body_bytes().is_err());
+    }
+}
@@ -0,0 +1,337 @@
+//! Record-or-replay cassettes.
+//!
+//! A cassette is a HAR file that a `MockServer` both replays from and records into. Requests the
+//! server does not replay are forwarded to a local upstream server, and the resulting entries are
+//! appended to the log and written back to the cassette file.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use base64;
+use base64::Engine;
+use chrono::Utc;
+use serde_json;
+
+use file;
+use http::{self, WireRequest, WireResponse};
+use Cache;
+use CacheState::Unknown;
+use Content;
+use Entry;
+use Header;
+use Log;
+use OptionalTiming::{NotApplicable, TimedContent};
+use Response;
+use Timing;
+
+/// Request headers that are not forwarded upstream. `Accept-Encoding` is dropped so that bodies
+/// are recorded uncompressed.
+const UNFORWARDED_HEADERS: &[&str] = &[
+    "accept-encoding",
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+];
+
+/// What a cassette does with incoming requests.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    /// Only replay recorded entries; unmatched requests get the server's fallback response.
+    ReplayOnly,
+
+    /// Replay recorded entries and record requests that match none of them.
+    RecordMissing,
+
+    /// Record every request, replacing the cassette's previous entries.
+    ReRecordAll,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Mode, String> {
+        match mode {
+            "replay-only" => Ok(Mode::ReplayOnly),
+            "record-missing" => Ok(Mode::RecordMissing),
+            "re-record-all" => Ok(Mode::ReRecordAll),
+            _ => Err(format!("unknown cassette mode \"{}\"", mode)),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Mode::ReplayOnly => "replay-only",
+            Mode::RecordMissing => "record-missing",
+            Mode::ReRecordAll => "re-record-all",
+        })
+    }
+}
+
+/// A HAR file to replay from and record into.
+pub struct Cassette {
+    path: PathBuf,
+    mode: Mode,
+    upstream: String,
+}
+
+impl Cassette {
+    /// Creates a cassette stored at `path`. Recorded requests are forwarded to `upstream`, a
+    /// `host:port` address of a plain HTTP server.
+    pub fn new<P: Into<PathBuf>>(path: P, mode: Mode, upstream: String) -> Cassette {
+        Cassette {
+            path: path.into(),
+            mode,
+            upstream,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Loads the log to replay.
+    ///
+    /// When recording, a missing file starts an empty log. In `ReRecordAll` mode the previous
+    /// pages, entries and the bodies they shared are discarded.
+    pub fn load(&self) -> serde_json::Result<Log> {
+        if self.mode != Mode::ReplayOnly && !self.path.exists() {
+            return Ok(Log::new(None, None));
+        }
+        let mut log = file::from_path(&self.path)?;
+        if self.mode == Mode::ReRecordAll {
+            log.pages = None;
+            log.entries.clear();
+            log.bodies.clear();
+        }
+        Ok(log)
+    }
+
+    /// Writes `log` back to the cassette file.
+    pub fn save(&self, log: &Log) -> serde_json::Result<()> {
+        file::to_path(&self.path, log)
+    }
+
+    /// Forwards `request` to the upstream server and records the exchange as an entry.
+    pub(crate) fn forward(&self, request: &WireRequest) -> io::Result<Entry> {
+        let mut headers: Vec<(String, String)> = request.headers
+            .iter()
+            .filter(|(name, _)| {
+                !UNFORWARDED_HEADERS.iter().any(|skipped| name.eq_ignore_ascii_case(skipped))
+            })
+            .cloned()
+            .collect();
+        headers.push(("Host".to_string(), self.upstream.clone()));
+        if !request.body.is_empty() {
+            headers.push(("Content-Length".to_string(), request.body.len().to_string()));
+        }
+        headers.push(("Connection".to_string(), "close".to_string()));
+
+        let started_date_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+        let start = Instant::now();
+        let stream = TcpStream::connect(self.upstream.as_str())?;
+        let connected = start.elapsed();
+        http::write_request(&mut BufWriter::new(&stream), &request.method, &request.target,
+                            &headers, &request.body)?;
+        let sent = start.elapsed();
+        let server_ip_address = stream.peer_addr()?.ip().to_string();
+        let connection = stream.local_addr()?.port().to_string();
+        let mut reader = BufReader::new(stream);
+        reader.fill_buf()?;
+        let first_byte = start.elapsed();
+        let response = http::read_response(&mut reader, request.method == "HEAD")?;
+        let received = start.elapsed();
+
+        Ok(Entry::new(
+            None,
+            started_date_time,
+            request.to_request(),
+            to_response(response),
+            Cache::new(Unknown, Unknown, None),
+            Timing::new(
+                NotApplicable,
+                NotApplicable,
+                TimedContent(millis(connected)),
+                millis(sent - connected),
+                millis(first_byte - sent),
+                millis(received - first_byte),
+                NotApplicable,
+                None
+            ),
+            Some(server_ip_address),
+            Some(connection),
+            None
+        ))
+    }
+}
+
+fn millis(duration: Duration) -> u32 {
+    duration.as_millis() as u32
+}
+
+fn to_response(response: WireResponse) -> Response {
+    let mime_type = response.header("content-type").unwrap_or("").to_string();
+    let redirect_url = response.header("location").unwrap_or("").to_string();
+    let size = response.body.len() as i32;
+    let (text, encoding) = match String::from_utf8(response.body) {
+        Ok(text) => (text, None),
+        Err(err) => {
+            let text = base64::engine::general_purpose::STANDARD.encode(err.as_bytes());
+            (text, Some("base64".to_string()))
+        }
+    };
+    let headers = response.headers
+        .into_iter()
+        .map(|(name, value)| Header::new(name, value, None))
+        .collect();
+    let mut response = Response::new(
+        response.status,
+        response.status_text,
+        response.version,
+        Vec::new(),
+        headers,
+        Content::new(size, None, mime_type, Some(text), encoding, None),
+        redirect_url,
+        None,
+        Some(size),
+        None
+    );
+    response.cookies_from_headers();
+    response.headers_size = response.compute_headers_size();
+    response
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use std::env;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::Arc;
+    use std::thread;
+
+    use cassette::{Cassette, Mode};
+    use file;
+    use serve::{self, MockServer};
+    use sidecar;
+    use SharedBody;
+
+    /// A stand-in upstream that answers every request with its own request line.
+    fn upstream() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_string();
+                let mut header = String::new();
+                while header != "\r\n" {
+                    header.clear();
+                    reader.read_line(&mut header).unwrap();
+                }
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\
+                                Content-Length: {}\r\n\r\n{}", line.len(), line).unwrap();
+            }
+        });
+        addr
+    }
+
+    fn start(cassette: Cassette) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = MockServer::from_cassette(cassette, serve::not_found()).unwrap();
+        thread::spawn(move || server.serve(listener));
+        addr
+    }
+
+    fn fetch(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn cassette_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("har-cassette-{}-{}.har", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_mode_from_str() {
+        assert_eq!("record-missing".parse(), Ok(Mode::RecordMissing));
+        assert_eq!(Mode::ReRecordAll.to_string(), "re-record-all");
+        assert!("record".parse::<Mode>().is_err());
+    }
+
+    #[test]
+    fn test_record_missing() {
+        let path = cassette_path("record-missing");
+        let upstream = upstream().to_string();
+
+        let addr = start(Cassette::new(path.clone(), Mode::RecordMissing, upstream.clone()));
+        assert!(fetch(addr, "/a").ends_with("GET /a HTTP/1.1"));
+        assert_eq!(file::from_path(&path).unwrap().entries().len(), 1);
+
+        // A fresh server replays /a from the cassette and only records /b.
+        let addr = start(Cassette::new(path.clone(), Mode::RecordMissing, upstream));
+        assert!(fetch(addr, "/a").ends_with("GET /a HTTP/1.1"));
+        assert!(fetch(addr, "/b").ends_with("GET /b HTTP/1.1"));
+        let log = file::from_path(&path).unwrap();
+        let urls: Vec<&str> = log.entries().iter().map(|entry| entry.request.url.as_str()).collect();
+        assert_eq!(urls.len(), 2);
+        assert!(urls[0].ends_with("/a") && urls[1].ends_with("/b"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_only() {
+        let path = cassette_path("replay-only");
+        let addr = start(Cassette::new(path.clone(), Mode::RecordMissing, upstream().to_string()));
+        fetch(addr, "/a");
+
+        // Nothing is listening on the upstream address any more; replay must not need it.
+        let addr = start(Cassette::new(path.clone(), Mode::ReplayOnly, "127.0.0.1:9".to_string()));
+        assert!(fetch(addr, "/a").ends_with("GET /a HTTP/1.1"));
+        assert!(fetch(addr, "/b").starts_with("HTTP/1.1 404 Not Found"));
+        assert_eq!(file::from_path(&path).unwrap().entries().len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_re_record_all() {
+        let path = cassette_path("re-record-all");
+        let upstream = upstream().to_string();
+        let addr = start(Cassette::new(path.clone(), Mode::RecordMissing, upstream.clone()));
+        fetch(addr, "/a");
+        fetch(addr, "/b");
+        let mut log = file::from_path(&path).unwrap();
+        let body = SharedBody { text: "old".to_string(), encoding: None };
+        log.bodies.insert(sidecar::hash(b"old"), Arc::new(body));
+        file::to_path(&path, &log).unwrap();
+
+        let addr = start(Cassette::new(path.clone(), Mode::ReRecordAll, upstream));
+        fetch(addr, "/b");
+        let log = file::from_path(&path).unwrap();
+        assert!(log.bodies.is_empty());
+        assert_eq!(log.entries().len(), 1);
+        assert!(log.entries()[0].request.url.ends_with("/b"));
+        fs::remove_file(&path).unwrap();
+    }
+}
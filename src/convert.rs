@@ -0,0 +1,118 @@
+//! Writing logs in formats other than HAR.
+
+use std::io::{self, Write};
+
+use serde_json;
+
+use Log;
+
+/// Columns written by `to_csv`.
+const CSV_COLUMNS: &[&str] = &[
+    "started", "method", "url", "status", "mime_type", "size", "time", "pageref",
+];
+
+/// Writes each entry of `log` as a JSON object on a line of its own (JSON Lines), for tools
+/// that process one record at a time.
+pub fn to_jsonl<W: Write>(mut writer: W, log: &Log) -> serde_json::Result<()> {
+    for entry in &log.entries {
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes one line per entry of `log` as CSV with a header row: start time, method, URL,
+/// status, MIME type, content size, total time in milliseconds and page.
+pub fn to_csv<W: Write>(mut writer: W, log: &Log) -> io::Result<()> {
+    writeln!(writer, "{}", CSV_COLUMNS.join(","))?;
+    for entry in &log.entries {
+        let response = &entry.response;
+        let fields = [
+            entry.started_date_time.clone(),
+            entry.request.method.clone(),
+            entry.request.url.clone(),
+            response.status.to_string(),
+            response.content.mime_type.clone(),
+            response.content.size.to_string(),
+            entry.timings.total().to_string(),
+            entry.pageref.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        writeln!(writer, "{}", fields.join(","))?;
+    }
+    writer.flush()
+}
+
+/// Quotes `field` if it contains a separator, quote or line break (RFC 4180).
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use serde_json::{self, Value};
+
+    use convert;
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn log() -> Log {
+        let mut log = Log::new(None, None);
+        for url in &["http://example.com/", "http://example.com/?a=1,2&b=\"x\""] {
+            log.add_entry(Entry::new(
+                None,
+                "2009-04-16T12:07:20.000Z".to_string(),
+                Request::new("GET".to_string(), url.to_string(), "HTTP/1.1".to_string(),
+                             Vec::new(), Vec::new(), Vec::new(), None, None, None, None),
+                Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                              Vec::new(),
+                              Content::new(5, None, "text/html".to_string(), None, None, None),
+                              "".to_string(), None, None, None),
+                Cache::new(Absent, Absent, None),
+                Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable,
+                            None),
+                None,
+                None,
+                None
+            ));
+        }
+        log
+    }
+
+    #[test]
+    fn test_to_jsonl() {
+        let mut out = Vec::new();
+        convert::to_jsonl(&mut out, &log()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let entry: Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(entry["request"]["url"],
+                   Value::String("http://example.com/?a=1,2&b=\"x\"".to_string()));
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let mut out = Vec::new();
+        convert::to_csv(&mut out, &log()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "started,method,url,status,mime_type,size,time,pageref\n\
+                    2009-04-16T12:07:20.000Z,GET,http://example.com/,200,text/html,5,6,\n\
+                    2009-04-16T12:07:20.000Z,GET,\"http://example.com/?a=1,2&b=\"\"x\"\"\",200,\
+                    text/html,5,6,\n");
+    }
+}
@@ -0,0 +1,279 @@
+//! Parsing and writing cookie headers (RFC 6265).
+//!
+//! Browsers usually record `cookies` alongside the headers, but tools that build logs from the
+//! wire only see the headers. `Request::cookies_from_headers` and
+//! `Response::cookies_from_headers` fill the cookie lists from the `Cookie` and `Set-Cookie`
+//! headers; `headers_from_cookies` goes the other way, so the two never disagree.
+//!
+//! HAR records `expires` in ISO 8601, so the HTTP dates of `Expires` attributes are converted
+//! both ways. `SameSite` and `Max-Age` have no HAR field and are kept in the custom `_sameSite`
+//! and `_maxAge` fields.
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+
+use Cookie;
+use Header;
+use Request;
+use Response;
+
+/// Formats of `Expires` dates besides RFC 1123 still seen in the wild.
+const HTTP_DATE_FORMATS: &[&str] = &[
+    "%a, %d-%b-%Y %H:%M:%S GMT",
+    "%a, %d-%b-%y %H:%M:%S GMT",
+    "%A, %d-%b-%y %H:%M:%S GMT",
+    "%a %b %e %H:%M:%S %Y",
+];
+
+/// Parses the value of a `Set-Cookie` header, following the algorithm of RFC 6265 section 5.2.
+///
+/// Returns `None` when the header must be ignored: there is no `=` in the name-value pair or
+/// the name is empty. Attributes with invalid values are skipped, and the last of repeated
+/// attributes wins. The leading dot of a domain is dropped.
+pub fn parse_set_cookie(value: &str) -> Option<Cookie> {
+    let mut parts = value.split(';');
+    let pair = parts.next().unwrap_or("");
+    let index = pair.find('=')?;
+    let name = pair[..index].trim();
+    if name.is_empty() {
+        return None;
+    }
+    let mut cookie = Cookie::new(name.to_string(), pair[index + 1..].trim().to_string(), None,
+                                 None, None, None, None, None);
+
+    for attribute in parts {
+        let (name, value) = match attribute.find('=') {
+            Some(index) => (attribute[..index].trim(), attribute[index + 1..].trim()),
+            None => (attribute.trim(), ""),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "expires" => {
+                if let Some(expires) = parse_http_date(value) {
+                    cookie.expires = Some(expires.to_rfc3339_opts(SecondsFormat::Millis, true));
+                }
+            }
+            "max-age" => {
+                let digits = value.strip_prefix('-').unwrap_or(value);
+                if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+                    cookie.max_age = value.parse().ok();
+                }
+            }
+            "domain" if !value.is_empty() => {
+                cookie.domain = Some(value.trim_start_matches('.').to_ascii_lowercase());
+            }
+            "path" if value.starts_with('/') => cookie.path = Some(value.to_string()),
+            "secure" => cookie.secure = Some(true),
+            "httponly" => cookie.http_only = Some(true),
+            "samesite" => {
+                let same_site = ["Strict", "Lax", "None"].iter()
+                    .find(|same_site| same_site.eq_ignore_ascii_case(value));
+                if let Some(same_site) = same_site {
+                    cookie.same_site = Some(same_site.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(cookie)
+}
+
+/// Parses the value of a `Cookie` header into its cookies, in order. A pair without `=` is read
+/// as a cookie with an empty value.
+pub fn parse_cookie_header(value: &str) -> Vec<Cookie> {
+    value.split(';')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = match pair.find('=') {
+                Some(index) => (pair[..index].trim(), pair[index + 1..].trim()),
+                None => (pair, ""),
+            };
+            Cookie::new(name.to_string(), value.to_string(), None, None, None, None, None, None)
+        })
+        .collect()
+}
+
+/// Writes `cookie` as the value of a `Set-Cookie` header. An `expires` that is not an ISO 8601
+/// date is left out.
+pub fn set_cookie_header(cookie: &Cookie) -> String {
+    let mut value = format!("{}={}", cookie.name, cookie.value);
+    if let Some(ref path) = cookie.path {
+        value.push_str(&format!("; Path={}", path));
+    }
+    if let Some(ref domain) = cookie.domain {
+        value.push_str(&format!("; Domain={}", domain));
+    }
+    if let Some(expires) = cookie.expires.as_ref().and_then(|expires| {
+        DateTime::parse_from_rfc3339(expires).ok()
+    }) {
+        let expires = expires.with_timezone(&Utc).format("%a, %d %b %Y %H:%M:%S GMT");
+        value.push_str(&format!("; Expires={}", expires));
+    }
+    if let Some(max_age) = cookie.max_age {
+        value.push_str(&format!("; Max-Age={}", max_age));
+    }
+    if cookie.http_only == Some(true) {
+        value.push_str("; HttpOnly");
+    }
+    if cookie.secure == Some(true) {
+        value.push_str("; Secure");
+    }
+    if let Some(ref same_site) = cookie.same_site {
+        value.push_str(&format!("; SameSite={}", same_site));
+    }
+    value
+}
+
+/// Writes `cookies` as the value of a `Cookie` header.
+pub fn cookie_header(cookies: &[Cookie]) -> String {
+    cookies.iter()
+        .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Parses an HTTP date as used by `Expires`: RFC 1123 and the older formats browsers accept.
+pub(crate) fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc2822(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+    HTTP_DATE_FORMATS.iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|date| date.and_utc())
+}
+
+/// Replaces the headers named `name` with `values`, at the position of the first one.
+fn replace_headers(headers: &mut Vec<Header>, name: &str, values: Vec<String>) {
+    let position = headers.iter()
+        .position(|header| header.name.eq_ignore_ascii_case(name))
+        .unwrap_or(headers.len());
+    headers.retain(|header| !header.name.eq_ignore_ascii_case(name));
+    let position = position.min(headers.len());
+    let new = values.into_iter().map(|value| Header::new(name.to_string(), value, None));
+    headers.splice(position..position, new);
+}
+
+impl Request {
+    /// Replaces `cookies` with the cookies of the `Cookie` headers.
+    pub fn cookies_from_headers(&mut self) {
+        self.cookies = self.headers().get_all("cookie").into_iter()
+            .flat_map(parse_cookie_header)
+            .collect();
+    }
+
+    /// Replaces the `Cookie` headers with a single one holding `cookies`, or none if there are
+    /// no cookies.
+    pub fn headers_from_cookies(&mut self) {
+        let values = if self.cookies.is_empty() {
+            Vec::new()
+        } else {
+            vec![cookie_header(&self.cookies)]
+        };
+        replace_headers(&mut self.headers, "Cookie", values);
+    }
+}
+
+impl Response {
+    /// Replaces `cookies` with the cookies of the `Set-Cookie` headers, skipping the ones
+    /// RFC 6265 says to ignore.
+    pub fn cookies_from_headers(&mut self) {
+        self.cookies = self.headers().get_all("set-cookie").into_iter()
+            .filter_map(parse_set_cookie)
+            .collect();
+    }
+
+    /// Replaces the `Set-Cookie` headers with one per cookie in `cookies`.
+    pub fn headers_from_cookies(&mut self) {
+        let values = self.cookies.iter().map(set_cookie_header).collect();
+        replace_headers(&mut self.headers, "Set-Cookie", values);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use cookie;
+    use Content;
+    use Cookie;
+    use Header;
+    use Request;
+    use Response;
+
+    fn header(name: &str, value: &str) -> Header {
+        Header::new(name.to_string(), value.to_string(), None)
+    }
+
+    #[test]
+    fn test_parse_set_cookie() {
+        let cookie = cookie::parse_set_cookie(
+            "id=a3fWa; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=2592000; \
+             Domain=.Example.com; Path=/docs; Secure; HttpOnly; SameSite=lax").unwrap();
+        assert_eq!(cookie.name, "id");
+        assert_eq!(cookie.value, "a3fWa");
+        assert_eq!(cookie.expires.as_deref(), Some("2015-10-21T07:28:00.000Z"));
+        assert_eq!(cookie.max_age, Some(2592000));
+        assert_eq!(cookie.domain.as_deref(), Some("example.com"));
+        assert_eq!(cookie.path.as_deref(), Some("/docs"));
+        assert_eq!(cookie.secure, Some(true));
+        assert_eq!(cookie.http_only, Some(true));
+        assert_eq!(cookie.same_site.as_deref(), Some("Lax"));
+        assert_eq!(cookie::set_cookie_header(&cookie),
+                   "id=a3fWa; Path=/docs; Domain=example.com; \
+                    Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=2592000; HttpOnly; Secure; \
+                    SameSite=Lax");
+
+        let cookie = cookie::parse_set_cookie(
+            "old=1; expires=Thursday, 01-Jan-70 00:00:00 GMT; Max-Age=soon; Path=docs; \
+             SameSite=sometimes").unwrap();
+        assert_eq!(cookie, Cookie {
+            expires: Some("1970-01-01T00:00:00.000Z".to_string()),
+            ..Cookie::new("old".to_string(), "1".to_string(), None, None, None, None, None, None)
+        });
+
+        assert_eq!(cookie::parse_set_cookie("novalue; Path=/"), None);
+        assert_eq!(cookie::parse_set_cookie(" =x"), None);
+    }
+
+    #[test]
+    fn test_request_cookies() {
+        let mut request = Request::new("GET".to_string(), "http://example.com/".to_string(),
+                                       "HTTP/1.1".to_string(), Vec::new(),
+                                       vec![header("Accept", "*/*"),
+                                            header("cookie", "a=1; b = 2;flag"),
+                                            header("Cookie", "c=3")],
+                                       Vec::new(), None, None, None, None);
+        request.cookies_from_headers();
+        let pairs: Vec<(&str, &str)> = request.cookies.iter()
+            .map(|cookie| (cookie.name.as_str(), cookie.value.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![("a", "1"), ("b", "2"), ("flag", ""), ("c", "3")]);
+
+        request.cookies.remove(2);
+        request.headers_from_cookies();
+        assert_eq!(request.headers, vec![header("Accept", "*/*"),
+                                         header("Cookie", "a=1; b=2; c=3")]);
+    }
+
+    #[test]
+    fn test_response_cookies() {
+        let mut response = Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(),
+                                         Vec::new(),
+                                         vec![header("Set-Cookie", "a=1; Path=/; Secure"),
+                                              header("Set-Cookie", "broken"),
+                                              header("Content-Type", "text/plain"),
+                                              header("set-cookie", "b=2; SameSite=Strict")],
+                                         Content::new(0, None, "text/plain".to_string(), None,
+                                                      None, None),
+                                         "".to_string(), None, None, None);
+        response.cookies_from_headers();
+        assert_eq!(response.cookies.len(), 2);
+
+        response.headers_from_cookies();
+        assert_eq!(response.headers, vec![header("Set-Cookie", "a=1; Path=/; Secure"),
+                                          header("Set-Cookie", "b=2; SameSite=Strict"),
+                                          header("Content-Type", "text/plain")]);
+        let cookies = response.cookies.clone();
+        response.cookies_from_headers();
+        assert_eq!(response.cookies, cookies);
+    }
+}
@@ -0,0 +1,216 @@
+//! Storing repeated response bodies once.
+//!
+//! Crawls fetch the same scripts, styles and fonts again and again, and each copy is embedded in
+//! its entry. `Log::dedup_bodies` moves every body that occurs more than once into a table on the
+//! log, keyed by the SHA-256 hash of its bytes, and points each content at it. In memory the
+//! contents share a single copy; on disk the table is written once as the custom `_bodies` field
+//! and each content names its body in `_body`.
+//!
+//! `Content::body_bytes` reads shared bodies like embedded ones, and logs read through the `file`
+//! module are linked to their table. `Log::expand_bodies` embeds the bodies again for tools that
+//! read `content.text` directly.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use sidecar::hash;
+use Content;
+use Entry;
+use Log;
+use SharedBody;
+
+impl Log {
+    /// Shares every response body that occurs more than once in the log, or that is already
+    /// shared, returning how many copies were dropped.
+    ///
+    /// Bodies are compared by their bytes, so a base64 encoded copy and a plain text copy of the
+    /// same body are shared too. Bodies with an encoding this crate does not understand are left
+    /// alone.
+    pub fn dedup_bodies(&mut self) -> usize {
+        let hashes: Vec<Option<String>> = self.entries.iter()
+            .map(|entry| {
+                let content = &entry.response.content;
+                match content.text {
+                    Some(ref text) if !text.is_empty() => {
+                        content.body_bytes().ok().map(|body| hash(&body))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        let mut counts = HashMap::new();
+        for hash in hashes.iter().flatten() {
+            *counts.entry(hash.as_str()).or_insert(0) += 1;
+        }
+
+        let mut shared = 0;
+        let mut stored = 0;
+        for (entry, hash) in self.entries.iter_mut().zip(&hashes) {
+            let hash = match *hash {
+                Some(ref hash) if counts[hash.as_str()] > 1 || self.bodies.contains_key(hash) => {
+                    hash
+                }
+                _ => continue,
+            };
+            let content = &mut entry.response.content;
+            let text = content.text.take().unwrap_or_default();
+            let encoding = content.encoding.take();
+            let body = self.bodies.entry(hash.clone()).or_insert_with(|| {
+                stored += 1;
+                Arc::new(SharedBody { text, encoding })
+            });
+            content.body = Some(hash.clone());
+            content.shared = Some(body.clone());
+            shared += 1;
+        }
+        shared - stored
+    }
+
+    /// Embeds every shared response body in its content again and empties the table.
+    pub fn expand_bodies(&mut self) {
+        for entry in &mut self.entries {
+            let content = &mut entry.response.content;
+            if let Some(body) = content.shared.take() {
+                content.body = None;
+                content.text = Some(body.text.clone());
+                content.encoding = body.encoding.clone();
+            }
+        }
+        self.bodies.clear();
+    }
+
+    /// Runs `rewrite` on every entry with its response body embedded, sharing the bodies again
+    /// afterwards if any were shared. Rewrites that depend on the entry, such as scrubbing JSON
+    /// bodies only, can then never leave a shared copy untouched.
+    pub(crate) fn rewrite_entries<F: FnMut(&mut Entry)>(&mut self, mut rewrite: F) {
+        let shared = !self.bodies.is_empty();
+        if shared {
+            self.expand_bodies();
+        }
+        for entry in &mut self.entries {
+            rewrite(entry);
+        }
+        if shared {
+            self.dedup_bodies();
+        }
+    }
+
+    /// Adds the body `content` shares, if any, to this log's table, for an entry that came from
+    /// another log.
+    pub(crate) fn adopt_body(&mut self, content: &Content) {
+        if let (Some(hash), Some(body)) = (&content.body, &content.shared) {
+            self.bodies.entry(hash.clone()).or_insert_with(|| body.clone());
+        }
+    }
+
+    /// Points each content that names a shared body at the copy in the table, as read from a
+    /// file.
+    pub(crate) fn link_bodies(&mut self) {
+        for entry in &mut self.entries {
+            let content = &mut entry.response.content;
+            if let Some(ref hash) = content.body {
+                content.shared = self.bodies.get(hash).cloned();
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::Arc;
+
+    use file;
+    use filter::Filter;
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn log() -> Log {
+        let mut log = Log::new(None, None);
+        let bodies = [
+            (Some("aGVsbG8="), Some("base64")),
+            (Some("hello"), None),
+            (Some("other"), None),
+            (Some("aGVsbG8="), Some("base64")),
+            (None, None),
+        ];
+        for &(text, encoding) in &bodies {
+            log.add_entry(Entry::new(
+                None,
+                "2009-04-16T12:07:20.000Z".to_string(),
+                Request::new("GET".to_string(), "http://example.com/app.js".to_string(),
+                             "HTTP/1.1".to_string(), Vec::new(), Vec::new(), Vec::new(), None,
+                             None, None, None),
+                Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                              Vec::new(),
+                              Content::new(5, None, "text/javascript".to_string(),
+                                           text.map(str::to_string), encoding.map(str::to_string),
+                                           None),
+                              "".to_string(), None, None, None),
+                Cache::new(Absent, Absent, None),
+                Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable,
+                            None),
+                None,
+                None,
+                None
+            ));
+        }
+        log
+    }
+
+    fn bodies(log: &Log) -> Vec<Vec<u8>> {
+        log.entries.iter().map(|entry| entry.response.content.body_bytes().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_dedup_bodies() {
+        let original = log();
+        let mut log = original.clone();
+        assert_eq!(log.dedup_bodies(), 2);
+        assert_eq!(log.bodies.len(), 1);
+        assert_eq!(bodies(&log), bodies(&original));
+
+        let contents: Vec<&Content> = log.entries.iter()
+            .map(|entry| &entry.response.content)
+            .collect();
+        assert_eq!(contents[0].text, None);
+        assert!(Arc::ptr_eq(contents[0].shared.as_ref().unwrap(),
+                            contents[1].shared.as_ref().unwrap()));
+        assert_eq!(contents[2].text, Some("other".to_string()));
+
+        // Sharing again changes nothing.
+        assert_eq!(log.dedup_bodies(), 0);
+
+        log.expand_bodies();
+        assert!(log.bodies.is_empty());
+        assert_eq!(bodies(&log), bodies(&original));
+    }
+
+    #[test]
+    fn test_dedup_round_trip() {
+        let mut log = log();
+        log.dedup_bodies();
+
+        let mut written = Vec::new();
+        file::to_writer(&mut written, &log).unwrap();
+        let text = String::from_utf8(written.clone()).unwrap();
+        assert_eq!(text.matches("aGVsbG8=").count(), 1);
+        assert_eq!(text.matches("\"_body\"").count(), 3);
+
+        let read = file::from_reader(&written[..]).unwrap();
+        assert_eq!(read, log);
+        assert_eq!(bodies(&read), bodies(&log));
+
+        // Entries taken out of the log take their shared bodies with them.
+        let subset = read.filtered(&Filter::status(200));
+        assert_eq!(subset.bodies, read.bodies);
+    }
+}
@@ -0,0 +1,646 @@
+//! Structural comparison of two logs.
+//!
+//! Entries are paired by method and normalized URL: scheme and host are lower-cased, default
+//! ports and fragments dropped and query parameters sorted. Entries sharing a key are paired in
+//! log order, so the second `GET /poll` of one log is compared with the second of the other.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+use serde_json::{self, Value};
+use url::form_urlencoded;
+use url::Url;
+
+use Content;
+use Cookie;
+use Entry;
+use Header;
+use Log;
+use OptionalTiming;
+use Timing;
+
+/// Text bodies whose differing middle parts would need a larger comparison table than this are
+/// reported as replaced wholesale.
+const MAX_LINE_DIFF_CELLS: usize = 4_000_000;
+
+/// The differences between an old and a new log.
+#[derive(PartialEq, Debug)]
+pub struct LogDiff<'a> {
+    /// Entries only present in the new log, in log order.
+    pub added: Vec<&'a Entry>,
+
+    /// Entries only present in the old log, in log order.
+    pub removed: Vec<&'a Entry>,
+
+    /// Entries present in both logs, in the order of the old log.
+    pub paired: Vec<EntryDiff<'a>>,
+}
+
+impl<'a> LogDiff<'a> {
+    /// Whether the logs have the same requests with the same results, ignoring timings.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() &&
+            self.paired.iter().all(|entry| !entry.has_changes())
+    }
+}
+
+/// The differences between two entries for the same request.
+#[derive(PartialEq, Debug)]
+pub struct EntryDiff<'a> {
+    pub old: &'a Entry,
+    pub new: &'a Entry,
+
+    /// Old and new response status, if they differ.
+    pub status: Option<(i32, i32)>,
+
+    /// Request headers that differ, by lower-cased name.
+    pub request_headers: Vec<Change>,
+
+    /// Response headers that differ, by lower-cased name.
+    pub response_headers: Vec<Change>,
+
+    /// Request cookies that differ.
+    pub request_cookies: Vec<Change>,
+
+    /// Response cookies that differ.
+    pub response_cookies: Vec<Change>,
+
+    /// Old and new size of the response content, if they differ.
+    pub body_size: Option<(i32, i32)>,
+
+    /// How the response content differs, if it does.
+    pub content: Option<ContentDiff>,
+
+    /// New minus old time spent in each phase.
+    pub timings: TimingDelta,
+}
+
+impl<'a> EntryDiff<'a> {
+    /// Whether anything other than the timings differs.
+    pub fn has_changes(&self) -> bool {
+        self.status.is_some() || !self.request_headers.is_empty() ||
+            !self.response_headers.is_empty() || !self.request_cookies.is_empty() ||
+            !self.response_cookies.is_empty() || self.body_size.is_some() ||
+            self.content.is_some()
+    }
+}
+
+/// A named value that was added, removed or changed. Repeated headers are joined with ", ".
+#[derive(PartialEq, Debug)]
+pub struct Change {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// How the response content of two entries differs.
+#[derive(PartialEq, Debug)]
+pub enum ContentDiff {
+    /// Both bodies are JSON; these values differ.
+    Json(Vec<JsonChange>),
+
+    /// These lines differ.
+    Text(Vec<LineChange>),
+
+    /// The recorded bodies differ, but at least one cannot be decoded for this reason.
+    Undecodable(String),
+}
+
+/// A JSON value that was added, removed or changed, located by a path such as `$.items[2].id`.
+#[derive(PartialEq, Debug)]
+pub struct JsonChange {
+    pub path: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// A line only present in one of two texts, with its (one based) line number in that text.
+#[derive(PartialEq, Debug)]
+pub enum LineChange {
+    Removed(usize, String),
+    Added(usize, String),
+}
+
+/// Differences in milliseconds between two sets of timings. Phases that do not apply count as
+/// zero.
+#[derive(Default, PartialEq, Debug)]
+pub struct TimingDelta {
+    pub blocked: i64,
+    pub dns: i64,
+    pub connect: i64,
+    pub ssl: i64,
+    pub send: i64,
+    pub wait: i64,
+    pub receive: i64,
+    pub total: i64,
+}
+
+impl TimingDelta {
+    fn new(old: &Timing, new: &Timing) -> TimingDelta {
+        let optional = |old: &OptionalTiming, new: &OptionalTiming| {
+            i64::from(new.millis().unwrap_or(0)) - i64::from(old.millis().unwrap_or(0))
+        };
+        TimingDelta {
+            blocked: optional(&old.blocked, &new.blocked),
+            dns: optional(&old.dns, &new.dns),
+            connect: optional(&old.connect, &new.connect),
+            ssl: optional(&old.ssl, &new.ssl),
+            send: i64::from(new.send) - i64::from(old.send),
+            wait: i64::from(new.wait) - i64::from(old.wait),
+            receive: i64::from(new.receive) - i64::from(old.receive),
+            total: i64::from(new.total()) - i64::from(old.total()),
+        }
+    }
+
+    fn phases(&self) -> Vec<(&'static str, i64)> {
+        vec![
+            ("blocked", self.blocked),
+            ("dns", self.dns),
+            ("connect", self.connect),
+            ("ssl", self.ssl),
+            ("send", self.send),
+            ("wait", self.wait),
+            ("receive", self.receive),
+        ]
+    }
+}
+
+/// Compares `old` with `new`.
+pub fn diff<'a>(old: &'a Log, new: &'a Log) -> LogDiff<'a> {
+    let mut unpaired: HashMap<String, VecDeque<usize>> = HashMap::new();
+    for (index, entry) in new.entries.iter().enumerate() {
+        unpaired.entry(pairing_key(entry)).or_default().push_back(index);
+    }
+
+    let mut removed = Vec::new();
+    let mut paired = Vec::new();
+    let mut paired_new = vec![false; new.entries.len()];
+    for entry in &old.entries {
+        match unpaired.get_mut(&pairing_key(entry)).and_then(|indices| indices.pop_front()) {
+            Some(index) => {
+                paired_new[index] = true;
+                paired.push(diff_entries(entry, &new.entries[index]));
+            }
+            None => removed.push(entry),
+        }
+    }
+    let added = new.entries
+        .iter()
+        .zip(paired_new)
+        .filter(|&(_, paired)| !paired)
+        .map(|(entry, _)| entry)
+        .collect();
+
+    LogDiff { added, removed, paired }
+}
+
+fn pairing_key(entry: &Entry) -> String {
+    format!("{} {}", entry.request.method.to_ascii_uppercase(), normalize_url(&entry.request.url))
+}
+
+fn normalize_url(url: &str) -> String {
+    let mut url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return url.to_string(),
+    };
+    url.set_fragment(None);
+    if url.query().is_some() {
+        let mut pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        pairs.sort();
+        let query = form_urlencoded::Serializer::new(String::new()).extend_pairs(pairs).finish();
+        url.set_query(Some(&query));
+    }
+    url.to_string()
+}
+
+fn diff_entries<'a>(old: &'a Entry, new: &'a Entry) -> EntryDiff<'a> {
+    let (old_response, new_response) = (&old.response, &new.response);
+    EntryDiff {
+        old,
+        new,
+        status: if old_response.status != new_response.status {
+            Some((old_response.status, new_response.status))
+        } else {
+            None
+        },
+        request_headers: diff_headers(&old.request.headers, &new.request.headers),
+        response_headers: diff_headers(&old_response.headers, &new_response.headers),
+        request_cookies: diff_cookies(&old.request.cookies, &new.request.cookies),
+        response_cookies: diff_cookies(&old_response.cookies, &new_response.cookies),
+        body_size: if old_response.content.size != new_response.content.size {
+            Some((old_response.content.size, new_response.content.size))
+        } else {
+            None
+        },
+        content: diff_content(&old_response.content, &new_response.content),
+        timings: TimingDelta::new(&old.timings, &new.timings),
+    }
+}
+
+fn diff_values(old: BTreeMap<String, String>, mut new: BTreeMap<String, String>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (name, old_value) in old {
+        match new.remove(&name) {
+            Some(ref new_value) if *new_value == old_value => {}
+            new_value => changes.push(Change { name, old: Some(old_value), new: new_value }),
+        }
+    }
+    for (name, new_value) in new {
+        changes.push(Change { name, old: None, new: Some(new_value) });
+    }
+    changes.sort_by(|a, b| a.name.cmp(&b.name));
+    changes
+}
+
+fn diff_headers(old: &[Header], new: &[Header]) -> Vec<Change> {
+    let joined = |headers: &[Header]| {
+        let mut joined: BTreeMap<String, String> = BTreeMap::new();
+        for header in headers {
+            let value = joined.entry(header.name.to_ascii_lowercase()).or_default();
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+            value.push_str(&header.value);
+        }
+        joined
+    };
+    diff_values(joined(old), joined(new))
+}
+
+fn diff_cookies(old: &[Cookie], new: &[Cookie]) -> Vec<Change> {
+    let values = |cookies: &[Cookie]| {
+        cookies.iter()
+            .map(|cookie| (cookie.name.clone(), cookie.value.clone()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    diff_values(values(old), values(new))
+}
+
+fn diff_content(old: &Content, new: &Content) -> Option<ContentDiff> {
+    if old.text == new.text && old.encoding == new.encoding && old.body == new.body {
+        return None;
+    }
+    let (old_bytes, new_bytes) = match (old.body_bytes(), new.body_bytes()) {
+        (Ok(old_bytes), Ok(new_bytes)) => (old_bytes, new_bytes),
+        (Err(err), _) | (_, Err(err)) => return Some(ContentDiff::Undecodable(err.to_string())),
+    };
+    if old_bytes == new_bytes {
+        return None;
+    }
+    if old.mime_type.contains("json") || new.mime_type.contains("json") {
+        let old_json = serde_json::from_slice::<Value>(&old_bytes);
+        let new_json = serde_json::from_slice::<Value>(&new_bytes);
+        if let (Ok(old_json), Ok(new_json)) = (old_json, new_json) {
+            let mut changes = Vec::new();
+            diff_json("$".to_string(), Some(&old_json), Some(&new_json), &mut changes);
+            return if changes.is_empty() { None } else { Some(ContentDiff::Json(changes)) };
+        }
+    }
+    let old_text = String::from_utf8_lossy(&old_bytes);
+    let new_text = String::from_utf8_lossy(&new_bytes);
+    Some(ContentDiff::Text(diff_lines(&old_text, &new_text)))
+}
+
+fn json_key_path(path: &str, key: &str) -> String {
+    let simple = !key.is_empty() &&
+        key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') &&
+        !key.starts_with(|c: char| c.is_ascii_digit());
+    if simple {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}[{}]", path, Value::String(key.to_string()))
+    }
+}
+
+fn diff_json(path: String,
+             old: Option<&Value>,
+             new: Option<&Value>,
+             changes: &mut Vec<JsonChange>) {
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+            for key in keys {
+                diff_json(json_key_path(&path, key), old.get(key), new.get(key), changes);
+            }
+        }
+        (Some(Value::Array(old)), Some(Value::Array(new))) => {
+            for index in 0..old.len().max(new.len()) {
+                diff_json(format!("{}[{}]", path, index), old.get(index), new.get(index), changes);
+            }
+        }
+        _ if old != new => changes.push(JsonChange {
+            path,
+            old: old.cloned(),
+            new: new.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_lines(old: &str, new: &str) -> Vec<LineChange> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    let removed = |index: usize| {
+        LineChange::Removed(prefix + index + 1, old_middle[index].to_string())
+    };
+    let added = |index: usize| {
+        LineChange::Added(prefix + index + 1, new_middle[index].to_string())
+    };
+
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if n.saturating_mul(m) > MAX_LINE_DIFF_CELLS {
+        return (0..n).map(removed).chain((0..m).map(added)).collect();
+    }
+
+    // lcs[i * (m + 1) + j] is the length of the longest common subsequence of old_middle[i..]
+    // and new_middle[j..].
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * (m + 1) + j] = if old_middle[i] == new_middle[j] {
+                lcs[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+            };
+        }
+    }
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old_middle[i] == new_middle[j] {
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1] {
+            changes.push(removed(i));
+            i += 1;
+        } else {
+            changes.push(added(j));
+            j += 1;
+        }
+    }
+    changes.extend((i..n).map(removed));
+    changes.extend((j..m).map(added));
+    changes
+}
+
+fn describe(value: &Option<String>) -> String {
+    match *value {
+        Some(ref value) => value.clone(),
+        None => "(none)".to_string(),
+    }
+}
+
+fn describe_json(value: &Option<Value>) -> String {
+    match *value {
+        Some(ref value) => value.to_string(),
+        None => "(none)".to_string(),
+    }
+}
+
+fn write_changes(f: &mut fmt::Formatter, label: &str, changes: &[Change]) -> fmt::Result {
+    for change in changes {
+        writeln!(f, "    {} {}: {} -> {}",
+                 label, change.name, describe(&change.old), describe(&change.new))?;
+    }
+    Ok(())
+}
+
+impl<'a> fmt::Display for EntryDiff<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "~ {} {}", self.new.request.method, self.new.request.url)?;
+        if let Some((old, new)) = self.status {
+            writeln!(f, "    status: {} -> {}", old, new)?;
+        }
+        write_changes(f, "request header", &self.request_headers)?;
+        write_changes(f, "request cookie", &self.request_cookies)?;
+        write_changes(f, "response header", &self.response_headers)?;
+        write_changes(f, "response cookie", &self.response_cookies)?;
+        if let Some((old, new)) = self.body_size {
+            writeln!(f, "    body size: {} -> {}", old, new)?;
+        }
+        match self.content {
+            Some(ContentDiff::Json(ref changes)) => {
+                for change in changes {
+                    writeln!(f, "    body {}: {} -> {}",
+                             change.path, describe_json(&change.old), describe_json(&change.new))?;
+                }
+            }
+            Some(ContentDiff::Text(ref changes)) => {
+                for change in changes {
+                    match *change {
+                        LineChange::Removed(line, ref text) => {
+                            writeln!(f, "    body line -{}: {}", line, text)?
+                        }
+                        LineChange::Added(line, ref text) => {
+                            writeln!(f, "    body line +{}: {}", line, text)?
+                        }
+                    }
+                }
+            }
+            Some(ContentDiff::Undecodable(ref reason)) => {
+                writeln!(f, "    body: differs, cannot decode: {}", reason)?;
+            }
+            None => {}
+        }
+        if self.timings.total != 0 {
+            let phases: Vec<String> = self.timings.phases()
+                .into_iter()
+                .filter(|&(_, delta)| delta != 0)
+                .map(|(phase, delta)| format!("{} {:+}ms", phase, delta))
+                .collect();
+            writeln!(f, "    time: {:+}ms ({})", self.timings.total, phases.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for LogDiff<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.removed {
+            writeln!(f, "- {} {}", entry.request.method, entry.request.url)?;
+        }
+        for entry in &self.added {
+            writeln!(f, "+ {} {}", entry.request.method, entry.request.url)?;
+        }
+        let mut unchanged = true;
+        for entry in &self.paired {
+            if entry.has_changes() || entry.timings.total != 0 {
+                unchanged = false;
+                write!(f, "{}", entry)?;
+            }
+        }
+        if unchanged && self.removed.is_empty() && self.added.is_empty() {
+            writeln!(f, "no differences")?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use serde_json::Value;
+
+    use diff::{self, Change, ContentDiff, JsonChange, LineChange};
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Header;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(method: &str, url: &str, status: i32, headers: Vec<(&str, &str)>, mime_type: &str,
+             text: &str, wait: u32) -> Entry {
+        Entry::new(
+            None,
+            "2009-04-16T12:07:23.596Z".to_string(),
+            Request::new(method.to_string(), url.to_string(), "HTTP/1.1".to_string(),
+                         Vec::new(), Vec::new(), Vec::new(), None, None, None, None),
+            Response::new(
+                status,
+                "".to_string(),
+                "HTTP/1.1".to_string(),
+                Vec::new(),
+                headers.into_iter()
+                    .map(|(name, value)| Header::new(name.to_string(), value.to_string(), None))
+                    .collect(),
+                Content::new(text.len() as i32, None, mime_type.to_string(),
+                             Some(text.to_string()), None, None),
+                "".to_string(),
+                None,
+                None,
+                None
+            ),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, wait, 3, NotApplicable,
+                        None),
+            None,
+            None,
+            None
+        )
+    }
+
+    fn log(entries: Vec<Entry>) -> Log {
+        let mut log = Log::new(None, None);
+        for entry in entries {
+            log.add_entry(entry);
+        }
+        log
+    }
+
+    #[test]
+    fn test_diff_pairs_by_method_and_normalized_url() {
+        let old = log(vec![
+            entry("GET", "HTTP://Example.com:80/a?y=2&x=1#top", 200, vec![], "text/plain", "", 5),
+            entry("GET", "http://example.com/removed", 200, vec![], "text/plain", "", 5),
+            entry("POST", "http://example.com/a?x=1&y=2", 200, vec![], "text/plain", "", 5),
+        ]);
+        let new = log(vec![
+            entry("GET", "http://example.com/a?x=1&y=2", 200, vec![], "text/plain", "", 5),
+            entry("GET", "http://example.com/added", 200, vec![], "text/plain", "", 5),
+            entry("post", "http://example.com/a?y=2&x=1", 200, vec![], "text/plain", "", 5),
+        ]);
+        let diff = diff::diff(&old, &new);
+        assert_eq!(diff.removed, vec![&old.entries()[1]]);
+        assert_eq!(diff.added, vec![&new.entries()[1]]);
+        assert_eq!(diff.paired.len(), 2);
+        assert!(diff.paired[0].new == &new.entries()[0]);
+        assert!(diff.paired[1].new == &new.entries()[2]);
+        assert!(!diff.paired.iter().any(|entry| entry.has_changes()));
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn test_diff_entry_changes() {
+        let old = log(vec![entry("GET", "http://example.com/", 200,
+                                 vec![("Content-Type", "text/html"), ("X-Old", "1")],
+                                 "text/html", "<p>\nhello\n</p>", 5)]);
+        let new = log(vec![entry("GET", "http://example.com/", 404,
+                                 vec![("content-type", "text/html"), ("X-New", "2")],
+                                 "text/html", "<p>\nworld\n</p>", 35)]);
+        let diff = diff::diff(&old, &new);
+        let entry = &diff.paired[0];
+        assert_eq!(entry.status, Some((200, 404)));
+        assert_eq!(entry.response_headers, vec![
+            Change { name: "x-new".to_string(), old: None, new: Some("2".to_string()) },
+            Change { name: "x-old".to_string(), old: Some("1".to_string()), new: None },
+        ]);
+        assert_eq!(entry.content, Some(ContentDiff::Text(vec![
+            LineChange::Removed(2, "hello".to_string()),
+            LineChange::Added(2, "world".to_string()),
+        ])));
+        assert_eq!(entry.body_size, None);
+        assert_eq!(entry.timings.wait, 30);
+        assert_eq!(entry.timings.total, 30);
+    }
+
+    #[test]
+    fn test_diff_json_content() {
+        let old_text = "{\"user\": {\"name\": \"a\", \"id\": 1}, \"tags\": [\"x\"]}";
+        let new_text = "{\"tags\":[\"x\",\"y\"],\"user\":{\"id\":1,\"name\":\"b\"}}";
+        let old = log(vec![entry("GET", "http://example.com/api", 200, vec![], "application/json",
+                                 old_text, 5)]);
+        let new = log(vec![entry("GET", "http://example.com/api", 200, vec![], "application/json",
+                                 new_text, 5)]);
+        let diff = diff::diff(&old, &new);
+        assert_eq!(diff.paired[0].content, Some(ContentDiff::Json(vec![
+            JsonChange {
+                path: "$.tags[1]".to_string(),
+                old: None,
+                new: Some(Value::String("y".to_string())),
+            },
+            JsonChange {
+                path: "$.user.name".to_string(),
+                old: Some(Value::String("a".to_string())),
+                new: Some(Value::String("b".to_string())),
+            },
+        ])));
+
+        // Bodies that fail to decode are not taken for equal.
+        let mut old_entry = entry("GET", "http://example.com/api", 200, vec![], "text/plain",
+                                  "not base64!", 5);
+        let mut new_entry = entry("GET", "http://example.com/api", 200, vec![], "text/plain",
+                                  "also not base64!", 5);
+        old_entry.response.content.encoding = Some("base64".to_string());
+        new_entry.response.content.encoding = Some("base64".to_string());
+        let (old, new) = (log(vec![old_entry]), log(vec![new_entry]));
+        let diff = diff::diff(&old, &new);
+        match diff.paired[0].content {
+            Some(ContentDiff::Undecodable(_)) => {}
+            ref content => panic!("expected an undecodable body, got {:?}", content),
+        }
+    }
+
+    #[test]
+    fn test_diff_report() {
+        let old = log(vec![
+            entry("GET", "http://example.com/", 200, vec![], "application/json", "{\"a\":1}", 5),
+            entry("GET", "http://example.com/same", 200, vec![], "text/plain", "", 5),
+            entry("GET", "http://example.com/old", 200, vec![], "text/plain", "", 5),
+        ]);
+        let new = log(vec![
+            entry("GET", "http://example.com/", 500, vec![], "application/json", "{\"a\":2}", 8),
+            entry("GET", "http://example.com/same", 200, vec![], "text/plain", "", 5),
+        ]);
+        assert_eq!(diff::diff(&old, &new).to_string(),
+                   "- GET http://example.com/old\n\
+                    ~ GET http://example.com/\n    \
+                    status: 200 -> 500\n    \
+                    body $.a: 1 -> 2\n    \
+                    time: +3ms (wait +3ms)\n");
+        assert_eq!(diff::diff(&old, &old).to_string(), "no differences\n");
+        assert!(diff::diff(&old, &old).is_empty());
+    }
+}
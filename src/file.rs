@@ -0,0 +1,308 @@
+//! Reading and writing HAR files.
+//!
+//! A HAR file is a JSON document whose root object holds the `log` object under the key "log".
+//! Files are written pretty-printed with fields in a fixed order, so that captures of the same
+//! traffic produce small diffs. Optional fields that are `None` are left out rather than written
+//! as `null`, and both forms are read.
+//!
+//! HARs compress well, so gzip and zstd compressed documents are read transparently, recognized
+//! by their magic bytes. `to_path` compresses according to the file extension: `.gz` for gzip
+//! and `.zst` for zstd.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2;
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::de::{self, Deserialize, Deserializer, MapVisitor, Visitor};
+use serde::de::impls::IgnoredAny;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json;
+use zstd;
+
+use Log;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression level used for zstd; the library's default.
+const ZSTD_LEVEL: i32 = 3;
+
+/// How a HAR document is compressed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The compression implied by the extension of `path`: `.gz` for gzip and `.zst` for zstd.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Compression {
+        match path.as_ref().extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// The compression of a document starting with `bytes`, recognized by its magic number.
+    pub fn detect(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+// The root object is read and written by hand rather than derived: the derives expand to
+// impls inside a function body, which current compilers warn about for every such struct.
+struct Document {
+    log: Log,
+}
+
+struct DocumentRef<'a> {
+    log: &'a Log,
+}
+
+impl Deserialize for Document {
+    fn deserialize<D>(deserializer: D) -> Result<Document, D::Error>
+        where D: Deserializer
+    {
+        deserializer.deserialize_map(DocumentVisitor)
+    }
+}
+
+struct DocumentVisitor;
+
+impl Visitor for DocumentVisitor {
+    type Value = Document;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a HAR document")
+    }
+
+    fn visit_map<V>(self, mut visitor: V) -> Result<Document, V::Error>
+        where V: MapVisitor
+    {
+        let mut log = None;
+        while let Some(key) = visitor.visit_key::<String>()? {
+            if key == "log" {
+                log = Some(visitor.visit_value()?);
+            } else {
+                visitor.visit_value::<IgnoredAny>()?;
+            }
+        }
+        match log {
+            Some(log) => Ok(Document { log }),
+            None => Err(de::Error::missing_field("log")),
+        }
+    }
+}
+
+impl<'a> Serialize for DocumentRef<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry("log", self.log)?;
+        map.end()
+    }
+}
+
+/// Reads a HAR document from `reader`, decompressing it if it is gzip or zstd compressed.
+pub fn from_reader<R: Read>(mut reader: R) -> serde_json::Result<Log> {
+    let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+    (&mut reader).take(ZSTD_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    let compression = Compression::detect(&magic);
+    let reader = BufReader::new(io::Cursor::new(magic).chain(reader));
+    let document: Document = match compression {
+        Compression::None => serde_json::from_reader(reader)?,
+        Compression::Gzip => serde_json::from_reader(GzDecoder::new(reader))?,
+        Compression::Zstd => serde_json::from_reader(zstd::Decoder::with_buffer(reader)?)?,
+    };
+    let mut log = document.log;
+    log.link_bodies();
+    Ok(log)
+}
+
+/// Reads the HAR file at `path`, decompressing it if it is gzip or zstd compressed.
+pub fn from_path<P: AsRef<Path>>(path: P) -> serde_json::Result<Log> {
+    let file = fs::File::open(path)?;
+    from_reader(file)
+}
+
+/// Writes `log` to `writer` as a HAR document.
+pub fn to_writer<W: Write>(mut writer: W, log: &Log) -> serde_json::Result<()> {
+    serde_json::to_writer_pretty(&mut writer, &DocumentRef { log })?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes `log` to `writer` as a HAR document compressed with `compression`.
+pub fn to_writer_compressed<W: Write>(writer: W,
+                                      log: &Log,
+                                      compression: Compression) -> serde_json::Result<()> {
+    match compression {
+        Compression::None => to_writer(writer, log),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+            to_writer(&mut encoder, log)?;
+            encoder.finish()?;
+            Ok(())
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, ZSTD_LEVEL)?;
+            to_writer(&mut encoder, log)?;
+            encoder.finish()?;
+            Ok(())
+        }
+    }
+}
+
+/// Writes `log` to the HAR file at `path`, compressed as its extension implies.
+///
+/// The document is written to a temporary file next to `path` first and then moved into place,
+/// so readers never observe a partially written file.
+pub fn to_path<P: AsRef<Path>>(path: P, log: &Log) -> serde_json::Result<()> {
+    let path = path.as_ref();
+    let mut temporary = OsString::from(path.as_os_str());
+    temporary.push(".tmp");
+    let writer = BufWriter::new(fs::File::create(&temporary)?);
+    to_writer_compressed(writer, log, Compression::from_path(path))?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use file::{self, Compression};
+    use Browser;
+    use Cache;
+    use CacheState::{Absent, Unknown};
+    use Content;
+    use Entry;
+    use Log;
+    use OptionalTiming::{NotApplicable, TimedContent};
+    use Page;
+    use PageTimings;
+    use Request;
+    use Response;
+    use Timing;
+
+    #[test]
+    fn test_from_reader() {
+        let har = "{
+                       \"log\": {
+                           \"version\": \"1.2\",
+                           \"creator\": {
+                               \"name\": \"Rust-HAR\",
+                               \"version\": \"0.0.4\"
+                           },
+                           \"entries\": []
+                       }
+                   }";
+        let log = file::from_reader(har.as_bytes()).unwrap();
+        assert_eq!(log, Log::new(None, None));
+    }
+
+    #[test]
+    fn test_from_reader_no_envelope() {
+        let har = "{
+                       \"version\": \"1.2\",
+                       \"creator\": {
+                           \"name\": \"Rust-HAR\",
+                           \"version\": \"0.0.4\"
+                       },
+                       \"entries\": []
+                   }";
+        assert!(file::from_reader(har.as_bytes()).is_err());
+    }
+
+    fn log() -> Log {
+        let mut log = Log::new(Some(Browser::new("Firefox".to_string(), "3.6".to_string(), None)),
+                               None);
+        log.add_page(Page::new(
+            "2009-04-16T12:07:25.123+01:00".to_string(),
+            "page_0".to_string(),
+            "Test Page".to_string(),
+            PageTimings::new(TimedContent(1720), NotApplicable, None),
+            None
+        ));
+        log.add_entry(Entry::new(
+            Some("page_0".to_string()),
+            "2009-04-16T12:07:23.596Z".to_string(),
+            Request::new("GET".to_string(), "http://www.example.com/".to_string(),
+                         "HTTP/1.1".to_string(), Vec::new(), Vec::new(), Vec::new(), None,
+                         Some(150), Some(0), None),
+            Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(), Vec::new(),
+                          Content::new(5, None, "text/plain".to_string(),
+                                       Some("hello".to_string()), None, None),
+                          "".to_string(), None, None, None),
+            Cache::new(Absent, Unknown, None),
+            Timing::new(NotApplicable, TimedContent(1), NotApplicable, 4, 5, 6, NotApplicable,
+                        None),
+            None,
+            None,
+            None
+        ));
+        log
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let log = log();
+        let mut written = Vec::new();
+        file::to_writer(&mut written, &log).unwrap();
+        let text = String::from_utf8(written).unwrap();
+        assert!(text.contains("\"blocked\": -1"));
+        assert!(!text.contains("afterRequest"));
+        assert!(!text.contains("comment"));
+        assert!(text.ends_with("}\n"));
+        assert_eq!(file::from_reader(text.as_bytes()).unwrap(), log);
+    }
+
+    #[test]
+    fn test_compression() {
+        assert_eq!(Compression::from_path("a.har"), Compression::None);
+        assert_eq!(Compression::from_path("a.har.gz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("a.har.zst"), Compression::Zstd);
+        assert_eq!(Compression::detect(b"{"), Compression::None);
+        assert_eq!(Compression::detect(b""), Compression::None);
+
+        let log = log();
+        for &compression in &[Compression::None, Compression::Gzip, Compression::Zstd] {
+            let mut written = Vec::new();
+            file::to_writer_compressed(&mut written, &log, compression).unwrap();
+            assert_eq!(Compression::detect(&written), compression);
+            assert_eq!(file::from_reader(&written[..]).unwrap(), log);
+        }
+    }
+
+    #[test]
+    fn test_compressed_path() {
+        let log = log();
+        for extension in &["har", "har.gz", "har.zst"] {
+            let path = env::temp_dir().join(format!("har-file-{}.{}", process::id(), extension));
+            file::to_path(&path, &log).unwrap();
+            let bytes = fs::read(&path).unwrap();
+            assert_eq!(Compression::detect(&bytes), Compression::from_path(&path));
+            assert_eq!(file::from_path(&path).unwrap(), log);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
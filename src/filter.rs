@@ -0,0 +1,394 @@
+//! Selecting entries of a log.
+//!
+//! A `Filter` is a predicate over entries, built from simple conditions and combined with `and`,
+//! `or` and `not`:
+//!
+//! ```
+//! use har::filter::Filter;
+//!
+//! let failed_api_calls = Filter::host("api.example.com")
+//!     .and(Filter::path("/v1/*"))
+//!     .and(Filter::status_class(4).or(Filter::status_class(5)));
+//! ```
+
+use chrono::{DateTime, FixedOffset};
+use url::Url;
+
+use Entry;
+use Header;
+use Log;
+
+/// A predicate over entries.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    predicate: Predicate,
+}
+
+#[derive(Clone, Debug)]
+enum Predicate {
+    Any,
+    Host(String),
+    Path(String),
+    Method(String),
+    Status(i32),
+    StatusClass(i32),
+    MimeType(String),
+    MinSize(i64),
+    MaxSize(i64),
+    StartedAfter(DateTime<FixedOffset>),
+    StartedBefore(DateTime<FixedOffset>),
+    Page(String),
+    HasHeader(String),
+    HeaderValue(String, String),
+    BodyContains(String),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    fn new(predicate: Predicate) -> Filter {
+        Filter { predicate }
+    }
+
+    /// Matches every entry.
+    pub fn any() -> Filter {
+        Filter::new(Predicate::Any)
+    }
+
+    /// Matches entries whose URL host matches the glob `pattern`, e.g. `*.example.com`.
+    /// Hosts are compared case-insensitively.
+    pub fn host(pattern: &str) -> Filter {
+        Filter::new(Predicate::Host(pattern.to_ascii_lowercase()))
+    }
+
+    /// Matches entries whose URL path matches the glob `pattern`. `*` matches any run of
+    /// characters, including `/`, and `?` matches a single character.
+    pub fn path(pattern: &str) -> Filter {
+        Filter::new(Predicate::Path(pattern.to_string()))
+    }
+
+    /// Matches entries with the request method `method`, compared case-insensitively.
+    pub fn method(method: &str) -> Filter {
+        Filter::new(Predicate::Method(method.to_string()))
+    }
+
+    /// Matches entries with the response status `status`.
+    pub fn status(status: i32) -> Filter {
+        Filter::new(Predicate::Status(status))
+    }
+
+    /// Matches entries whose response status is in the class `class`, e.g. 4 for 4xx.
+    pub fn status_class(class: i32) -> Filter {
+        Filter::new(Predicate::StatusClass(class))
+    }
+
+    /// Matches entries whose response MIME type, without parameters, matches the glob
+    /// `pattern`, e.g. `image/*`.
+    pub fn mime_type(pattern: &str) -> Filter {
+        Filter::new(Predicate::MimeType(pattern.to_ascii_lowercase()))
+    }
+
+    /// Matches entries whose response content is at least `bytes` long.
+    pub fn min_size(bytes: i64) -> Filter {
+        Filter::new(Predicate::MinSize(bytes))
+    }
+
+    /// Matches entries whose response content is at most `bytes` long.
+    pub fn max_size(bytes: i64) -> Filter {
+        Filter::new(Predicate::MaxSize(bytes))
+    }
+
+    /// Matches entries started at or after `time`. Entries with an unparsable start never
+    /// match.
+    pub fn started_after(time: DateTime<FixedOffset>) -> Filter {
+        Filter::new(Predicate::StartedAfter(time))
+    }
+
+    /// Matches entries started before `time`. Entries with an unparsable start never match.
+    pub fn started_before(time: DateTime<FixedOffset>) -> Filter {
+        Filter::new(Predicate::StartedBefore(time))
+    }
+
+    /// Matches entries belonging to the page with id `id`.
+    pub fn page(id: &str) -> Filter {
+        Filter::new(Predicate::Page(id.to_string()))
+    }
+
+    /// Matches entries whose request or response has a header called `name`.
+    pub fn has_header(name: &str) -> Filter {
+        Filter::new(Predicate::HasHeader(name.to_string()))
+    }
+
+    /// Matches entries whose request or response has a header called `name` whose value matches
+    /// the glob `pattern`.
+    pub fn header_value(name: &str, pattern: &str) -> Filter {
+        Filter::new(Predicate::HeaderValue(name.to_string(), pattern.to_string()))
+    }
+
+    /// Matches entries whose request or (decoded) response body contains `text`.
+    pub fn body_contains(text: &str) -> Filter {
+        Filter::new(Predicate::BodyContains(text.to_string()))
+    }
+
+    /// Matches entries matched by both filters.
+    pub fn and(self, other: Filter) -> Filter {
+        Filter::new(Predicate::And(Box::new(self), Box::new(other)))
+    }
+
+    /// Matches entries matched by either filter.
+    pub fn or(self, other: Filter) -> Filter {
+        Filter::new(Predicate::Or(Box::new(self), Box::new(other)))
+    }
+
+    /// Matches entries not matched by this filter.
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Filter {
+        Filter::new(Predicate::Not(Box::new(self)))
+    }
+
+    /// Whether `entry` is selected by this filter.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let request = &entry.request;
+        let response = &entry.response;
+        match self.predicate {
+            Predicate::Any => true,
+            Predicate::Host(ref pattern) => {
+                Url::parse(&request.url).ok()
+                    .and_then(|url| url.host_str().map(|host| glob_match(pattern, host)))
+                    .unwrap_or(false)
+            }
+            Predicate::Path(ref pattern) => {
+                Url::parse(&request.url).is_ok_and(|url| glob_match(pattern, url.path()))
+            }
+            Predicate::Method(ref method) => request.method.eq_ignore_ascii_case(method),
+            Predicate::Status(status) => response.status == status,
+            Predicate::StatusClass(class) => response.status / 100 == class,
+            Predicate::MimeType(ref pattern) => {
+                let mime_type = response.content.mime_type.split(';').next().unwrap_or("");
+                glob_match(pattern, &mime_type.trim().to_ascii_lowercase())
+            }
+            Predicate::MinSize(bytes) => i64::from(response.content.size) >= bytes,
+            Predicate::MaxSize(bytes) => i64::from(response.content.size) <= bytes,
+            Predicate::StartedAfter(time) => entry.started().is_some_and(|started| started >= time),
+            Predicate::StartedBefore(time) => entry.started().is_some_and(|started| started < time),
+            Predicate::Page(ref id) => entry.pageref.as_ref() == Some(id),
+            Predicate::HasHeader(ref name) => {
+                headers(entry).any(|header| header.name.eq_ignore_ascii_case(name))
+            }
+            Predicate::HeaderValue(ref name, ref pattern) => {
+                headers(entry).any(|header| {
+                    header.name.eq_ignore_ascii_case(name) && glob_match(pattern, &header.value)
+                })
+            }
+            Predicate::BodyContains(ref text) => {
+                let in_request = request.post_data.as_ref()
+                    .is_some_and(|post_data| post_data.text.contains(text.as_str()));
+                in_request || response.content.body_bytes().is_ok_and(|body| {
+                    String::from_utf8_lossy(&body).contains(text.as_str())
+                })
+            }
+            Predicate::And(ref a, ref b) => a.matches(entry) && b.matches(entry),
+            Predicate::Or(ref a, ref b) => a.matches(entry) || b.matches(entry),
+            Predicate::Not(ref filter) => !filter.matches(entry),
+        }
+    }
+}
+
+fn headers(entry: &Entry) -> impl Iterator<Item = &Header> {
+    entry.request.headers.iter().chain(entry.response.headers.iter())
+}
+
+/// Matches `text` against a glob where `*` matches any run of characters and `?` matches one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the most recent `*`: its position in the pattern, and the position
+    // in the text it currently extends to.
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, extent)) = backtrack {
+            backtrack = Some((star, extent + 1));
+            p = star + 1;
+            t = extent + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl Log {
+    /// Iterates over the entries selected by `filter`, in log order.
+    pub fn filter<'a>(&'a self, filter: &'a Filter) -> impl Iterator<Item = &'a Entry> + 'a {
+        self.entries.iter().filter(move |entry| filter.matches(entry))
+    }
+
+    /// A copy of this log holding only the entries selected by `filter` and the pages they
+    /// refer to.
+    pub fn filtered(&self, filter: &Filter) -> Log {
+        self.subset(self.filter(filter).collect())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use chrono::DateTime;
+
+    use filter::{self, Filter};
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Header;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Page;
+    use PageTimings;
+    use PostData;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(method: &str, url: &str, status: i32, mime_type: &str, text: &str,
+             started_date_time: &str) -> Entry {
+        Entry::new(
+            Some("page_0".to_string()),
+            started_date_time.to_string(),
+            Request::new(
+                method.to_string(),
+                url.to_string(),
+                "HTTP/1.1".to_string(),
+                Vec::new(),
+                vec![Header::new("Authorization".to_string(), "Bearer abc".to_string(), None)],
+                Vec::new(),
+                if method == "POST" {
+                    Some(PostData::new("text/plain".to_string(), Vec::new(),
+                                       "posted needle".to_string(), None))
+                } else {
+                    None
+                },
+                None,
+                None,
+                None
+            ),
+            Response::new(
+                status,
+                "".to_string(),
+                "HTTP/1.1".to_string(),
+                Vec::new(),
+                vec![Header::new("Cache-Control".to_string(), "no-cache".to_string(), None)],
+                Content::new(text.len() as i32, None, mime_type.to_string(),
+                             Some(text.to_string()), None, None),
+                "".to_string(),
+                None,
+                None,
+                None
+            ),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable, None),
+            None,
+            None,
+            None
+        )
+    }
+
+    fn log() -> Log {
+        let mut log = Log::new(None, None);
+        log.add_page(Page::new("2009-04-16T12:07:20.000Z".to_string(), "page_0".to_string(),
+                               "Test Page".to_string(),
+                               PageTimings::new(NotApplicable, NotApplicable, None), None));
+        log.add_entry(entry("GET", "http://www.example.com/index.html", 200,
+                            "text/html; charset=utf-8", "<html>hello</html>",
+                            "2009-04-16T12:07:20.000Z"));
+        log.add_entry(entry("GET", "http://cdn.example.com/img/logo.png", 304, "image/png", "",
+                            "2009-04-16T12:07:21.000Z"));
+        log.add_entry(entry("POST", "http://api.other.org/v1/users", 503, "application/json",
+                            "{\"error\": \"unavailable\"}", "2009-04-16T12:07:22.000Z"));
+        log
+    }
+
+    fn urls(log: &Log, filter: &Filter) -> Vec<String> {
+        log.filter(filter).map(|entry| entry.request.url.clone()).collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(filter::glob_match("*.example.com", "cdn.example.com"));
+        assert!(!filter::glob_match("*.example.com", "example.com"));
+        assert!(filter::glob_match("/v1/*/items", "/v1/a/b/items"));
+        assert!(filter::glob_match("/img/logo.???", "/img/logo.png"));
+        assert!(!filter::glob_match("/img/logo.???", "/img/logo.jpeg"));
+        assert!(filter::glob_match("*", ""));
+    }
+
+    #[test]
+    fn test_filter_request() {
+        let log = log();
+        assert_eq!(urls(&log, &Filter::host("*.EXAMPLE.com")),
+                   vec!["http://www.example.com/index.html",
+                        "http://cdn.example.com/img/logo.png"]);
+        assert_eq!(urls(&log, &Filter::path("/v1/*")), vec!["http://api.other.org/v1/users"]);
+        assert_eq!(urls(&log, &Filter::method("post")), vec!["http://api.other.org/v1/users"]);
+        assert_eq!(urls(&log, &Filter::page("page_0")).len(), 3);
+        assert!(urls(&log, &Filter::page("page_1")).is_empty());
+    }
+
+    #[test]
+    fn test_filter_response() {
+        let log = log();
+        assert_eq!(urls(&log, &Filter::status_class(3)),
+                   vec!["http://cdn.example.com/img/logo.png"]);
+        assert_eq!(urls(&log, &Filter::status(503)), vec!["http://api.other.org/v1/users"]);
+        assert_eq!(urls(&log, &Filter::mime_type("text/html")),
+                   vec!["http://www.example.com/index.html"]);
+        assert_eq!(urls(&log, &Filter::mime_type("image/*")),
+                   vec!["http://cdn.example.com/img/logo.png"]);
+        assert_eq!(urls(&log, &Filter::min_size(1).and(Filter::max_size(18))),
+                   vec!["http://www.example.com/index.html"]);
+    }
+
+    #[test]
+    fn test_filter_time_headers_and_bodies() {
+        let log = log();
+        let after = DateTime::parse_from_rfc3339("2009-04-16T12:07:21.000Z").unwrap();
+        let before = DateTime::parse_from_rfc3339("2009-04-16T12:07:22.000Z").unwrap();
+        assert_eq!(urls(&log, &Filter::started_after(after).and(Filter::started_before(before))),
+                   vec!["http://cdn.example.com/img/logo.png"]);
+        assert_eq!(urls(&log, &Filter::has_header("authorization")).len(), 3);
+        assert_eq!(urls(&log, &Filter::header_value("cache-control", "no-*")).len(), 3);
+        assert!(urls(&log, &Filter::has_header("x-missing")).is_empty());
+        assert_eq!(urls(&log, &Filter::body_contains("hello")),
+                   vec!["http://www.example.com/index.html"]);
+        assert_eq!(urls(&log, &Filter::body_contains("needle")),
+                   vec!["http://api.other.org/v1/users"]);
+    }
+
+    #[test]
+    fn test_filter_combinators() {
+        let log = log();
+        let filter = Filter::status_class(2).or(Filter::status_class(5))
+            .and(Filter::method("GET").not());
+        assert_eq!(urls(&log, &filter), vec!["http://api.other.org/v1/users"]);
+        assert_eq!(urls(&log, &Filter::any()).len(), 3);
+    }
+
+    #[test]
+    fn test_filtered_log() {
+        let filtered = log().filtered(&Filter::host("nowhere.invalid"));
+        assert!(filtered.entries().is_empty());
+        assert!(filtered.pages.unwrap().is_empty());
+        let filtered = log().filtered(&Filter::method("POST"));
+        assert_eq!(filtered.entries().len(), 1);
+        assert_eq!(filtered.pages.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,421 @@
+//! Parsing and writing form bodies.
+//!
+//! HAR can record a posted form twice: as the body `text` and as its decoded `params`. Browsers
+//! fill in both, but logs built from the wire only have the text. `PostData::params_from_text`
+//! decodes `application/x-www-form-urlencoded` and `multipart/form-data` bodies into params, with
+//! the file name and content type of file parts, and `PostData::text_from_params` encodes the
+//! params back into the body.
+//!
+//! `Param.value` is a string, so file contents that are not UTF-8 are stored base64 encoded and
+//! marked with "base64" in the custom `_encoding` field.
+
+use std::error;
+use std::fmt;
+
+use base64;
+use base64::Engine;
+use url::form_urlencoded;
+
+use Param;
+use PostData;
+
+/// The reason a form body could not be decoded or encoded.
+#[derive(Debug, PartialEq)]
+pub enum FormError {
+    /// The MIME type is not a form type this module understands.
+    UnsupportedType(String),
+
+    /// A `multipart/form-data` MIME type without a boundary parameter.
+    MissingBoundary,
+
+    /// The multipart body does not follow RFC 7578.
+    Malformed(String),
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormError::UnsupportedType(ref mime_type) => {
+                write!(f, "\"{}\" is not a form MIME type", mime_type)
+            }
+            FormError::MissingBoundary => write!(f, "multipart MIME type without a boundary"),
+            FormError::Malformed(ref reason) => write!(f, "malformed multipart body: {}", reason),
+        }
+    }
+}
+
+impl error::Error for FormError {}
+
+/// Parses an `application/x-www-form-urlencoded` body into its params, in order.
+pub fn parse_urlencoded(text: &str) -> Vec<Param> {
+    form_urlencoded::parse(text.as_bytes())
+        .map(|(name, value)| {
+            Param::new(name.into_owned(), Some(value.into_owned()), None, None, None)
+        })
+        .collect()
+}
+
+/// Encodes `params` as an `application/x-www-form-urlencoded` body. Params without a value are
+/// written with an empty one.
+pub fn encode_urlencoded(params: &[Param]) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(params.iter().map(|param| {
+            (&param.name, param.value.as_deref().unwrap_or(""))
+        }))
+        .finish()
+}
+
+/// The `boundary` parameter of a `multipart/*` MIME type.
+pub fn boundary(mime_type: &str) -> Option<String> {
+    parameters(mime_type).into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value)
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Parses a `multipart/form-data` body delimited by `boundary` into its params, in order.
+///
+/// Parts with a `filename` become file params with the part's content type. Part contents in
+/// base64 transfer encoding are decoded.
+pub fn parse_multipart(body: &[u8], boundary: &str) -> Result<Vec<Param>, FormError> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut rest = match find(body, &delimiter) {
+        Some(0) => &body[delimiter.len()..],
+        Some(index) if body[..index].ends_with(b"\r\n") => &body[index + delimiter.len()..],
+        _ => return Err(FormError::Malformed("no opening boundary".to_string())),
+    };
+    let delimiter = [&b"\r\n"[..], &delimiter].concat();
+
+    let mut params = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(params);
+        }
+        let line_end = find(rest, b"\r\n")
+            .ok_or(FormError::Malformed("boundary not followed by a line break".to_string()))?;
+        if rest[..line_end].iter().any(|&b| b != b' ' && b != b'\t') {
+            return Err(FormError::Malformed("text after a boundary".to_string()));
+        }
+        rest = &rest[line_end + 2..];
+        let end = find(rest, &delimiter)
+            .ok_or(FormError::Malformed("no closing boundary".to_string()))?;
+        params.push(parse_part(&rest[..end])?);
+        rest = &rest[end + delimiter.len()..];
+    }
+}
+
+/// Encodes `params` as a `multipart/form-data` body delimited by `boundary`. Params with a file
+/// name are written as file parts.
+pub fn encode_multipart(params: &[Param], boundary: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    for param in params {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        let mut disposition = format!("Content-Disposition: form-data; name=\"{}\"",
+                                      escape_quoted(&param.name));
+        if let Some(ref file_name) = param.file_name {
+            disposition.push_str(&format!("; filename=\"{}\"", escape_quoted(file_name)));
+        }
+        body.extend_from_slice(disposition.as_bytes());
+        body.extend_from_slice(b"\r\n");
+        if let Some(ref content_type) = param.content_type {
+            body.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(&param_bytes(param));
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+fn parse_part(part: &[u8]) -> Result<Param, FormError> {
+    let (head, content) = if part.starts_with(b"\r\n") {
+        (&part[..0], &part[2..])
+    } else {
+        let end = find(part, b"\r\n\r\n")
+            .ok_or(FormError::Malformed("part headers not ended by an empty line".to_string()))?;
+        (&part[..end], &part[end + 4..])
+    };
+
+    let mut name = None;
+    let mut file_name = None;
+    let mut content_type = None;
+    let mut base64_encoded = false;
+    for line in String::from_utf8_lossy(head).split("\r\n") {
+        let (header, value) = match line.find(':') {
+            Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+            None => continue,
+        };
+        if header.eq_ignore_ascii_case("content-disposition") {
+            let mut extended_file_name = None;
+            for (parameter, value) in parameters(value) {
+                match parameter.to_ascii_lowercase().as_str() {
+                    "name" => name = Some(value),
+                    "filename" => file_name = Some(value),
+                    "filename*" => extended_file_name = decode_extended(&value),
+                    _ => {}
+                }
+            }
+            file_name = extended_file_name.or(file_name);
+        } else if header.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.to_string());
+        } else if header.eq_ignore_ascii_case("content-transfer-encoding") {
+            base64_encoded = value.eq_ignore_ascii_case("base64");
+        }
+    }
+    let name = name.ok_or(FormError::Malformed("part without a name".to_string()))?;
+
+    let decoded;
+    let content = if base64_encoded {
+        let text: Vec<u8> = content.iter().cloned().filter(|b| !b.is_ascii_whitespace()).collect();
+        decoded = base64::engine::general_purpose::STANDARD.decode(text).map_err(|err| {
+            FormError::Malformed(format!("invalid base64 part: {}", err))
+        })?;
+        &decoded[..]
+    } else {
+        content
+    };
+    let mut param = Param::new(name, None, file_name, content_type, None);
+    match String::from_utf8(content.to_vec()) {
+        Ok(text) => param.value = Some(text),
+        Err(_) => {
+            param.value = Some(base64::engine::general_purpose::STANDARD.encode(content));
+            param.encoding = Some("base64".to_string());
+        }
+    }
+    Ok(param)
+}
+
+/// The bytes of a param's value, decoding base64 values.
+fn param_bytes(param: &Param) -> Vec<u8> {
+    let value = param.value.as_deref().unwrap_or("");
+    match param.encoding {
+        Some(ref encoding) if encoding.eq_ignore_ascii_case("base64") => {
+            base64::engine::general_purpose::STANDARD
+                .decode(value)
+                .unwrap_or_else(|_| value.as_bytes().to_vec())
+        }
+        _ => value.as_bytes().to_vec(),
+    }
+}
+
+/// The `;`-separated parameters of a header value, after its first item, with quoted strings
+/// unquoted.
+fn parameters(value: &str) -> Vec<(String, String)> {
+    let mut parameters = Vec::new();
+    let mut chars = value.chars().peekable();
+    // Skip the first item, e.g. the MIME type or "form-data".
+    for c in chars.by_ref() {
+        if c == ';' {
+            break;
+        }
+    }
+    loop {
+        let mut name = String::new();
+        for c in chars.by_ref() {
+            if c == '=' || c == ';' {
+                break;
+            }
+            name.push(c);
+        }
+        let name = name.trim().to_string();
+        if name.is_empty() && chars.peek().is_none() {
+            return parameters;
+        }
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => value.extend(chars.next()),
+                    _ => value.push(c),
+                }
+            }
+            for c in chars.by_ref() {
+                if c == ';' {
+                    break;
+                }
+            }
+        } else {
+            for c in chars.by_ref() {
+                if c == ';' {
+                    break;
+                }
+                value.push(c);
+            }
+            value = value.trim().to_string();
+        }
+        if !name.is_empty() {
+            parameters.push((name, value));
+        }
+    }
+}
+
+/// Decodes an RFC 5987 extended value such as `UTF-8''na%C3%AFve.txt`.
+fn decode_extended(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let text = parts.next()?;
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let escaped = tail.get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(escaped) if b == b'%' => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else {
+        Some(bytes.iter().map(|&b| b as char).collect())
+    }
+}
+
+/// Escapes a name for a quoted Content-Disposition parameter the way browsers do.
+fn escape_quoted(value: &str) -> String {
+    value.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Whether `mime_type` names `expected`, ignoring case and parameters.
+pub(crate) fn is_type(mime_type: &str, expected: &str) -> bool {
+    mime_type.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case(expected)
+}
+
+impl PostData {
+    /// Replaces `params` with the params decoded from `text`, according to `mime_type`.
+    pub fn params_from_text(&mut self) -> Result<(), FormError> {
+        self.params = if is_type(&self.mime_type, "application/x-www-form-urlencoded") {
+            parse_urlencoded(&self.text)
+        } else if is_type(&self.mime_type, "multipart/form-data") {
+            let boundary = boundary(&self.mime_type).ok_or(FormError::MissingBoundary)?;
+            parse_multipart(self.text.as_bytes(), &boundary)?
+        } else {
+            return Err(FormError::UnsupportedType(self.mime_type.clone()));
+        };
+        Ok(())
+    }
+
+    /// Replaces `text` with `params` encoded according to `mime_type`. File contents that are not
+    /// UTF-8 cannot be held in `text` and are written lossily.
+    pub fn text_from_params(&mut self) -> Result<(), FormError> {
+        self.text = if is_type(&self.mime_type, "application/x-www-form-urlencoded") {
+            encode_urlencoded(&self.params)
+        } else if is_type(&self.mime_type, "multipart/form-data") {
+            let boundary = boundary(&self.mime_type).ok_or(FormError::MissingBoundary)?;
+            String::from_utf8_lossy(&encode_multipart(&self.params, &boundary)).into_owned()
+        } else {
+            return Err(FormError::UnsupportedType(self.mime_type.clone()));
+        };
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use form::{self, FormError};
+    use Param;
+    use PostData;
+
+    fn param(name: &str, value: &str) -> Param {
+        Param::new(name.to_string(), Some(value.to_string()), None, None, None)
+    }
+
+    #[test]
+    fn test_urlencoded() {
+        let mut post_data = PostData::new("application/x-www-form-urlencoded; charset=UTF-8"
+                                              .to_string(),
+                                          Vec::new(), "user=bob+smith&tag=a%26b&tag=".to_string(),
+                                          None);
+        post_data.params_from_text().unwrap();
+        assert_eq!(post_data.params,
+                   vec![param("user", "bob smith"), param("tag", "a&b"), param("tag", "")]);
+
+        post_data.params[0].value = Some("alice".to_string());
+        post_data.text_from_params().unwrap();
+        assert_eq!(post_data.text, "user=alice&tag=a%26b&tag=");
+    }
+
+    #[test]
+    fn test_multipart() {
+        let body = "preamble\r\n\
+                    --XyZ\r\n\
+                    Content-Disposition: form-data; name=\"title\"\r\n\
+                    \r\n\
+                    Hello; \"world\"\r\n\
+                    --XyZ  \r\n\
+                    content-disposition: form-data; name=\"upload\"; filename=\"a \\\"b\\\".txt\"; \
+                    filename*=UTF-8''na%C3%AFve.txt\r\n\
+                    Content-Type: text/plain\r\n\
+                    \r\n\
+                    line 1\r\nline 2\r\n\
+                    --XyZ\r\n\
+                    Content-Disposition: form-data; name=blob; filename=\"x.bin\"\r\n\
+                    Content-Type: application/octet-stream\r\n\
+                    Content-Transfer-Encoding: base64\r\n\
+                    \r\n\
+                    AP8K\r\n\
+                    --XyZ--\r\n\
+                    epilogue";
+        let mut post_data = PostData::new("multipart/form-data; boundary=\"XyZ\"".to_string(),
+                                          Vec::new(), body.to_string(), None);
+        post_data.params_from_text().unwrap();
+
+        let mut upload = Param::new("upload".to_string(), Some("line 1\r\nline 2".to_string()),
+                                    Some("naïve.txt".to_string()),
+                                    Some("text/plain".to_string()), None);
+        let mut blob = Param::new("blob".to_string(), Some("AP8K".to_string()),
+                                  Some("x.bin".to_string()),
+                                  Some("application/octet-stream".to_string()), None);
+        blob.encoding = Some("base64".to_string());
+        assert_eq!(post_data.params, vec![param("title", "Hello; \"world\""), upload.clone(),
+                                          blob.clone()]);
+
+        // Encoding and decoding again gives the same params.
+        upload.file_name = Some("a \"b\".txt".to_string());
+        post_data.params = vec![param("title", "x"), upload.clone(), blob.clone()];
+        let encoded = form::encode_multipart(&post_data.params, "XyZ");
+        assert!(encoded.starts_with(b"--XyZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\
+                                      \r\nx\r\n--XyZ\r\n"));
+        assert!(encoded.ends_with(b"\x00\xff\x0a\r\n--XyZ--\r\n"));
+        upload.file_name = Some("a %22b%22.txt".to_string());
+        assert_eq!(form::parse_multipart(&encoded, "XyZ").unwrap(),
+                   vec![param("title", "x"), upload, blob]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut post_data = PostData::new("multipart/form-data".to_string(), Vec::new(),
+                                          "".to_string(), None);
+        assert_eq!(post_data.params_from_text(), Err(FormError::MissingBoundary));
+        post_data = PostData::new("application/json".to_string(), Vec::new(), "{}".to_string(),
+                                  None);
+        assert_eq!(post_data.params_from_text(),
+                   Err(FormError::UnsupportedType("application/json".to_string())));
+        assert_eq!(form::parse_multipart(b"--b\r\nContent-Type: text/plain\r\n\r\nx\r\n--b--",
+                                         "b"),
+                   Err(FormError::Malformed("part without a name".to_string())));
+        assert_eq!(form::parse_multipart(b"--b\r\nContent-Disposition: form-data; name=a\r\n\r\nx",
+                                         "b"),
+                   Err(FormError::Malformed("no closing boundary".to_string())));
+    }
+}
@@ -0,0 +1,370 @@
+//! Case-insensitive access to header lists.
+//!
+//! HAR keeps headers as a list so that order, case and repeated names survive a round trip.
+//! `Request::headers` and `Response::headers` borrow that list as a `Headers` view that looks
+//! names up case-insensitively; `headers_mut` gives a `HeadersMut` that can also change it.
+//! Edits leave the position and spelling of the other headers alone.
+
+use std::fmt;
+
+use Header;
+use Request;
+use Response;
+
+/// A read-only view of a header list.
+#[derive(Clone, Copy, Debug)]
+pub struct Headers<'a> {
+    headers: &'a [Header],
+}
+
+/// A view of a header list that can also change it.
+#[derive(Debug)]
+pub struct HeadersMut<'a> {
+    headers: &'a mut Vec<Header>,
+}
+
+/// The directives of `Cache-Control` headers, in order. Names are lowercased; values are
+/// unquoted.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CacheControl {
+    pub directives: Vec<(String, Option<String>)>,
+}
+
+impl<'a> Headers<'a> {
+    pub fn new(headers: &'a [Header]) -> Headers<'a> {
+        Headers { headers }
+    }
+
+    /// The value of the first header named `name`.
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.headers.iter()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value.as_str())
+    }
+
+    /// The values of all headers named `name`, in order.
+    pub fn get_all(&self, name: &str) -> Vec<&'a str> {
+        self.headers.iter()
+            .filter(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value.as_str())
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// The full `Content-Type`, parameters included.
+    pub fn content_type(&self) -> Option<&'a str> {
+        self.get("content-type").map(str::trim)
+    }
+
+    /// The `Content-Length`, or `None` if it is missing, not a number, or repeated with
+    /// different values.
+    pub fn content_length(&self) -> Option<u64> {
+        let mut lengths = self.get_all("content-length").into_iter()
+            .flat_map(|value| value.split(','))
+            .map(|value| value.trim().parse::<u64>().ok());
+        let first = lengths.next()??;
+        if lengths.all(|length| length == Some(first)) {
+            Some(first)
+        } else {
+            None
+        }
+    }
+
+    pub fn location(&self) -> Option<&'a str> {
+        self.get("location").map(str::trim)
+    }
+
+    /// The directives of all `Cache-Control` headers, or `None` if there are none.
+    pub fn cache_control(&self) -> Option<CacheControl> {
+        let values = self.get_all("cache-control");
+        if values.is_empty() {
+            None
+        } else {
+            Some(CacheControl::parse(&values.join(",")))
+        }
+    }
+}
+
+impl<'a> HeadersMut<'a> {
+    pub fn new(headers: &'a mut Vec<Header>) -> HeadersMut<'a> {
+        HeadersMut { headers }
+    }
+
+    /// A read-only view of the same list, for the lookups and typed accessors of `Headers`.
+    pub fn view(&self) -> Headers<'_> {
+        Headers::new(self.headers)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.view().get(name)
+    }
+
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.view().get_all(name)
+    }
+
+    /// Sets the header named `name` to `value`, returning the value it replaces. The first
+    /// header with that name keeps its place and spelling and the others are removed; if there
+    /// is none, the header is appended.
+    pub fn insert(&mut self, name: &str, value: String) -> Option<String> {
+        let position = self.headers.iter()
+            .position(|header| header.name.eq_ignore_ascii_case(name));
+        match position {
+            Some(position) => {
+                let old = std::mem::replace(&mut self.headers[position].value, value);
+                let mut index = 0;
+                self.headers.retain(|header| {
+                    let keep = index <= position || !header.name.eq_ignore_ascii_case(name);
+                    index += 1;
+                    keep
+                });
+                Some(old)
+            }
+            None => {
+                self.append(name, value);
+                None
+            }
+        }
+    }
+
+    /// Adds a header at the end, after any with the same name.
+    pub fn append(&mut self, name: &str, value: String) {
+        self.headers.push(Header::new(name.to_string(), value, None));
+    }
+
+    /// Removes all headers named `name`, returning their values in order.
+    pub fn remove(&mut self, name: &str) -> Vec<String> {
+        let mut removed = Vec::new();
+        let mut kept = Vec::with_capacity(self.headers.len());
+        for header in self.headers.drain(..) {
+            if header.name.eq_ignore_ascii_case(name) {
+                removed.push(header.value);
+            } else {
+                kept.push(header);
+            }
+        }
+        *self.headers = kept;
+        removed
+    }
+
+    pub fn set_content_type(&mut self, content_type: &str) {
+        self.insert("Content-Type", content_type.to_string());
+    }
+
+    pub fn set_content_length(&mut self, length: u64) {
+        self.insert("Content-Length", length.to_string());
+    }
+
+    pub fn set_location(&mut self, location: &str) {
+        self.insert("Location", location.to_string());
+    }
+
+    pub fn set_cache_control(&mut self, cache_control: &CacheControl) {
+        self.insert("Cache-Control", cache_control.to_string());
+    }
+}
+
+impl CacheControl {
+    /// Parses a comma-separated list of directives. Commas inside quoted values do not split.
+    pub fn parse(value: &str) -> CacheControl {
+        let mut parts = Vec::new();
+        let mut part = String::new();
+        let (mut quoted, mut escaped) = (false, false);
+        for c in value.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    parts.push(std::mem::take(&mut part));
+                    continue;
+                }
+                _ => {}
+            }
+            part.push(c);
+        }
+        parts.push(part);
+
+        let directives = parts.iter()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .map(|part| match part.find('=') {
+                Some(index) => {
+                    let value = part[index + 1..].trim();
+                    let value = match value.strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"')) {
+                        Some(quoted) => unescape(quoted),
+                        None => value.to_string(),
+                    };
+                    (part[..index].trim().to_ascii_lowercase(), Some(value))
+                }
+                None => (part.to_ascii_lowercase(), None),
+            })
+            .collect();
+        CacheControl { directives }
+    }
+
+    pub fn contains(&self, directive: &str) -> bool {
+        self.directives.iter().any(|(name, _)| name.eq_ignore_ascii_case(directive))
+    }
+
+    /// The value of the first `directive` that has one.
+    pub fn value(&self, directive: &str) -> Option<&str> {
+        self.directives.iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(directive))
+            .find_map(|(_, value)| value.as_deref())
+    }
+
+    pub fn max_age(&self) -> Option<u64> {
+        self.value("max-age").and_then(|value| value.parse().ok())
+    }
+
+    pub fn s_maxage(&self) -> Option<u64> {
+        self.value("s-maxage").and_then(|value| value.parse().ok())
+    }
+
+    pub fn no_cache(&self) -> bool {
+        self.contains("no-cache")
+    }
+
+    pub fn no_store(&self) -> bool {
+        self.contains("no-store")
+    }
+}
+
+/// Drops the backslashes of the escapes in a quoted string.
+fn unescape(quoted: &str) -> String {
+    let mut value = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next()),
+            _ => value.push(c),
+        }
+    }
+    value
+}
+
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, value)) in self.directives.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", name)?;
+            if let Some(ref value) = *value {
+                let token = !value.is_empty() && value.chars().all(|c| {
+                    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+                });
+                if token {
+                    write!(f, "={}", value)?;
+                } else {
+                    write!(f, "=\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Request {
+    pub fn headers(&self) -> Headers<'_> {
+        Headers::new(&self.headers)
+    }
+
+    pub fn headers_mut(&mut self) -> HeadersMut<'_> {
+        HeadersMut::new(&mut self.headers)
+    }
+}
+
+impl Response {
+    pub fn headers(&self) -> Headers<'_> {
+        Headers::new(&self.headers)
+    }
+
+    pub fn headers_mut(&mut self) -> HeadersMut<'_> {
+        HeadersMut::new(&mut self.headers)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use headers::CacheControl;
+    use Header;
+    use Request;
+
+    fn header(name: &str, value: &str) -> Header {
+        Header::new(name.to_string(), value.to_string(), None)
+    }
+
+    fn request(headers: Vec<Header>) -> Request {
+        Request::new("GET".to_string(), "http://example.com/".to_string(), "HTTP/1.1".to_string(),
+                     Vec::new(), headers, Vec::new(), None, None, None, None)
+    }
+
+    #[test]
+    fn test_get() {
+        let request = request(vec![header("Accept", "*/*"),
+                                   header("content-type", " text/html; charset=utf-8"),
+                                   header("Content-Length", "12"),
+                                   header("content-length", "12"),
+                                   header("X-Tag", "a"),
+                                   header("x-tag", "b")]);
+        let headers = request.headers();
+        assert_eq!(headers.get("ACCEPT"), Some("*/*"));
+        assert_eq!(headers.get("missing"), None);
+        assert_eq!(headers.get_all("X-TAG"), vec!["a", "b"]);
+        assert_eq!(headers.content_type(), Some("text/html; charset=utf-8"));
+        assert_eq!(headers.content_length(), Some(12));
+        assert_eq!(headers.location(), None);
+
+        let conflicting = self::request(vec![header("Content-Length", "12"),
+                                             header("Content-Length", "13")]);
+        assert_eq!(conflicting.headers().content_length(), None);
+    }
+
+    #[test]
+    fn test_headers_mut() {
+        let mut request = request(vec![header("x-tag", "a"),
+                                       header("Accept", "*/*"),
+                                       header("X-Tag", "b")]);
+        {
+            let mut headers = request.headers_mut();
+            assert_eq!(headers.insert("X-TAG", "c".to_string()), Some("a".to_string()));
+            assert_eq!(headers.insert("Location", "/next".to_string()), None);
+            headers.append("Via", "1.1 a".to_string());
+            headers.append("via", "1.1 b".to_string());
+            headers.set_content_length(3);
+        }
+        assert_eq!(request.headers, vec![header("x-tag", "c"), header("Accept", "*/*"),
+                                         header("Location", "/next"), header("Via", "1.1 a"),
+                                         header("via", "1.1 b"), header("Content-Length", "3")]);
+
+        assert_eq!(request.headers_mut().remove("VIA"), vec!["1.1 a", "1.1 b"]);
+        assert_eq!(request.headers_mut().remove("via"), Vec::<String>::new());
+        assert_eq!(request.headers().location(), Some("/next"));
+        assert_eq!(request.headers.len(), 4);
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let request = request(vec![header("Cache-Control", "public, Max-Age=60"),
+                                   header("cache-control", "no-cache=\"Set-Cookie, Via\""),
+                                   header("Cache-Control", "ext=\"a\\\"b,c\"")]);
+        let cache_control = request.headers().cache_control().unwrap();
+        assert_eq!(cache_control.max_age(), Some(60));
+        assert_eq!(cache_control.s_maxage(), None);
+        assert!(cache_control.no_cache());
+        assert!(!cache_control.no_store());
+        assert_eq!(cache_control.value("no-cache"), Some("Set-Cookie, Via"));
+        assert_eq!(cache_control.value("ext"), Some("a\"b,c"));
+        assert_eq!(cache_control.to_string(),
+                   "public, max-age=60, no-cache=\"Set-Cookie, Via\", ext=\"a\\\"b,c\"");
+        assert_eq!(CacheControl::parse(&cache_control.to_string()), cache_control);
+    }
+}
@@ -0,0 +1,348 @@
+//! Just enough HTTP/1.x to serve recorded responses over a socket, and to forward requests to
+//! an upstream server while recording.
+
+use std::io::{self, BufRead, Read, Write};
+
+use Header;
+use PostData;
+use Request;
+
+/// A request as read from a client connection.
+pub struct WireRequest {
+    pub method: String,
+    pub target: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl WireRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Whether the connection should stay open once this request has been answered.
+    pub fn keep_alive(&self) -> bool {
+        match self.header("connection").map(|value| value.to_ascii_lowercase()) {
+            Some(ref value) if value == "close" => false,
+            Some(ref value) if value == "keep-alive" => true,
+            _ => self.version == "HTTP/1.1",
+        }
+    }
+
+    /// Describes this request as a HAR request. Origin-form targets are made absolute using the
+    /// Host header, and the query string, cookies and form params are filled from the URL, the
+    /// Cookie header and the body. The header size is that of the head as written back.
+    pub fn to_request(&self) -> Request {
+        let url = if self.target.starts_with('/') {
+            format!("http://{}{}", self.header("host").unwrap_or("localhost"), self.target)
+        } else {
+            self.target.clone()
+        };
+        let headers = self.headers
+            .iter()
+            .map(|(name, value)| Header::new(name.clone(), value.clone(), None))
+            .collect();
+        let post_data = if self.body.is_empty() {
+            None
+        } else {
+            let mut post_data = PostData::new(
+                self.header("content-type").unwrap_or("application/octet-stream").to_string(),
+                Vec::new(),
+                String::from_utf8_lossy(&self.body).into_owned(),
+                None
+            );
+            // Only form bodies have params.
+            let _ = post_data.params_from_text();
+            Some(post_data)
+        };
+        let mut request = Request::new(
+            self.method.clone(),
+            url,
+            self.version.clone(),
+            Vec::new(),
+            headers,
+            Vec::new(),
+            post_data,
+            None,
+            Some(self.body.len() as i32),
+            None
+        );
+        request.cookies_from_headers();
+        let _ = request.query_from_url();
+        request.headers_size = request.compute_headers_size();
+        request
+    }
+}
+
+/// A response as read from an upstream connection.
+pub struct WireResponse {
+    pub version: String,
+    pub status: i32,
+    pub status_text: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl WireResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+/// Returns the value of the first header called `name`, compared case-insensitively.
+pub fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The longest start line, header line or chunk size line accepted, terminator included.
+const MAX_LINE: u64 = 64 * 1024;
+
+/// The most header lines accepted in one header block or trailer.
+const MAX_HEADERS: usize = 256;
+
+/// Appends the next line of `reader` to `line` like `BufRead::read_line`, but fails with
+/// `InvalidData` instead of buffering a line longer than `MAX_LINE`.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<usize> {
+    let read = reader.take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(invalid("line too long"));
+    }
+    Ok(read)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed mid-message")
+}
+
+/// Reads one request from `reader`.
+///
+/// Returns `None` if the peer closed the connection before starting a new request.
+pub fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<WireRequest>> {
+    let mut line = String::new();
+    // Empty lines before the request line are ignored (RFC 7230, section 3.5).
+    while line.trim().is_empty() {
+        line.clear();
+        if read_line(reader, &mut line)? == 0 {
+            return Ok(None);
+        }
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) => {
+            (method.to_string(), target.to_string(), version.to_string())
+        }
+        _ => return Err(invalid("malformed request line")),
+    };
+    let headers = read_headers(reader)?;
+    let body = read_body(reader, &headers, false)?;
+    Ok(Some(WireRequest {
+        method,
+        target,
+        version,
+        headers,
+        body,
+    }))
+}
+
+/// Reads one response from `reader`. `head` tells whether it answers a HEAD request, which has
+/// no body whatever the headers say.
+pub fn read_response<R: BufRead>(reader: &mut R, head: bool) -> io::Result<WireResponse> {
+    let mut line = String::new();
+    if read_line(reader, &mut line)? == 0 {
+        return Err(truncated());
+    }
+    let line = line.trim_end_matches(['\r', '\n']);
+    let mut parts = line.splitn(3, ' ');
+    let version = parts.next().unwrap_or("").to_string();
+    let status = parts.next()
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("malformed status line"))?;
+    let status_text = parts.next().unwrap_or("").to_string();
+    let headers = read_headers(reader)?;
+    let has_body = !(head || status / 100 == 1 || status == 204 || status == 304);
+    let body = if has_body { read_body(reader, &headers, true)? } else { Vec::new() };
+    Ok(WireResponse {
+        version,
+        status,
+        status_text,
+        headers,
+        body,
+    })
+}
+
+/// Reads header lines up to and including the empty line that ends the header block. Fails
+/// with `InvalidData` on a block of more than `MAX_HEADERS` lines.
+pub fn read_headers<R: BufRead>(reader: &mut R) -> io::Result<Vec<(String, String)>> {
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if read_line(reader, &mut line)? == 0 {
+            return Err(truncated());
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(invalid("too many header lines"));
+        }
+        match line.find(':') {
+            Some(colon) => headers.push((line[..colon].trim().to_string(),
+                                         line[colon + 1..].trim().to_string())),
+            None => return Err(invalid("malformed header line")),
+        }
+    }
+}
+
+/// Reads a message body framed by `Transfer-Encoding: chunked` or `Content-Length`.
+///
+/// When neither header is present the body is empty, unless `until_eof` is set, in which case the
+/// body extends to the end of the stream (as for responses on a closing connection).
+pub fn read_body<R: BufRead>(reader: &mut R,
+                             headers: &[(String, String)],
+                             until_eof: bool) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let chunked = find_header(headers, "transfer-encoding")
+        .is_some_and(|value| value.to_ascii_lowercase().contains("chunked"));
+    if chunked {
+        loop {
+            let mut size_line = String::new();
+            if read_line(reader, &mut size_line)? == 0 {
+                return Err(truncated());
+            }
+            let size = size_line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size, 16)
+                .map_err(|_| invalid("malformed chunk size"))?;
+            if size == 0 {
+                // Trailers are not kept.
+                read_headers(reader)?;
+                return Ok(body);
+            }
+            read_exactly(reader, size as u64, &mut body)?;
+            let mut crlf = String::new();
+            read_line(reader, &mut crlf)?;
+        }
+    }
+    if let Some(length) = find_header(headers, "content-length") {
+        let length = length.parse().map_err(|_| invalid("malformed Content-Length"))?;
+        read_exactly(reader, length, &mut body)?;
+    } else if until_eof {
+        reader.read_to_end(&mut body)?;
+    }
+    Ok(body)
+}
+
+/// Appends the next `length` bytes of `reader` to `body`. The buffer grows with the bytes that
+/// actually arrive, so a peer cannot make it allocate by announcing a huge length.
+fn read_exactly<R: BufRead>(reader: &mut R, length: u64, body: &mut Vec<u8>) -> io::Result<()> {
+    if (reader.take(length).read_to_end(body)? as u64) < length {
+        return Err(truncated());
+    }
+    Ok(())
+}
+
+/// Writes a request line, header block and body.
+pub fn write_request<W: Write>(writer: &mut W,
+                               method: &str,
+                               target: &str,
+                               headers: &[(String, String)],
+                               body: &[u8]) -> io::Result<()> {
+    write!(writer, "{} {} HTTP/1.1\r\n", method, target)?;
+    for (name, value) in headers {
+        write!(writer, "{}: {}\r\n", name, value)?;
+    }
+    writer.write_all(b"\r\n")?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+/// Writes a status line and header block, including the empty line that ends it.
+pub fn write_head<W: Write>(writer: &mut W,
+                            status: i32,
+                            status_text: &str,
+                            headers: &[(String, String)]) -> io::Result<()> {
+    write!(writer, "HTTP/1.1 {} {}\r\n", status, status_text)?;
+    for (name, value) in headers {
+        write!(writer, "{}: {}\r\n", name, value)?;
+    }
+    writer.write_all(b"\r\n")
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use std::io::{Cursor, ErrorKind};
+
+    use http;
+
+    #[test]
+    fn test_read_request() {
+        let mut wire = Cursor::new(
+            "POST /submit?x=1 HTTP/1.1\r\nHost: example.com\r\nContent-Length: 5\r\n\r\nhello"
+        );
+        let request = http::read_request(&mut wire).unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.target, "/submit?x=1");
+        assert_eq!(request.header("HOST"), Some("example.com"));
+        assert_eq!(request.body, b"hello".to_vec());
+        assert!(request.keep_alive());
+        assert!(http::read_request(&mut wire).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_request_chunked() {
+        let mut wire = Cursor::new(
+            "PUT / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+             3\r\nabc\r\n2;ext=1\r\nde\r\n0\r\n\r\n"
+        );
+        let request = http::read_request(&mut wire).unwrap().unwrap();
+        assert_eq!(request.body, b"abcde".to_vec());
+        assert!(!request.keep_alive());
+    }
+
+    #[test]
+    fn test_read_response() {
+        let mut wire = Cursor::new(
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\n\r\nmissing"
+        );
+        let response = http::read_response(&mut wire, false).unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.status_text, "Not Found");
+        assert_eq!(response.header("content-type"), Some("text/plain"));
+        assert_eq!(response.body, b"missing".to_vec());
+    }
+
+    #[test]
+    fn test_read_request_malformed() {
+        let mut wire = Cursor::new("GARBAGE\r\n\r\n");
+        assert!(http::read_request(&mut wire).is_err());
+
+        // Announced lengths are not allocated up front.
+        let mut wire = Cursor::new("POST / HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\nab");
+        assert!(http::read_request(&mut wire).is_err());
+        let mut wire = Cursor::new(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffff\r\nab"
+        );
+        assert!(http::read_request(&mut wire).is_err());
+
+        // So are unbounded lines and header blocks.
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(100_000));
+        let error = http::read_request(&mut Cursor::new(long)).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let many = format!("GET / HTTP/1.1\r\n{}\r\n", "X-A: b\r\n".repeat(1000));
+        let error = http::read_request(&mut Cursor::new(many)).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let some = format!("GET / HTTP/1.1\r\n{}\r\n", "X-A: b\r\n".repeat(256));
+        assert_eq!(http::read_request(&mut Cursor::new(some)).unwrap().unwrap().headers.len(),
+                   256);
+    }
+}
@@ -0,0 +1,372 @@
+//! Reconstructing the browser's cookie jar across a log.
+//!
+//! `replay` walks the entries in `startedDateTime` order, storing the cookies each response sets
+//! with the domain, path and expiry rules of RFC 6265 sections 5.3 and 5.4, and works out which
+//! cookies each request should have sent. Comparing that with the cookies the request did send
+//! points at session bugs: a cookie the server set that went missing, or one that should have
+//! expired.
+//!
+//! The jar does not know the public suffix list, so it accepts cookies for domains such as
+//! `co.uk`, and it ignores `SameSite` since the log does not say which site started a request.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, FixedOffset};
+use url::{Host, Url};
+
+use cookie;
+use Cookie;
+use Entry;
+use Log;
+
+/// The cookies a browser holds.
+#[derive(Clone, Debug, Default)]
+pub struct Jar {
+    cookies: Vec<Stored>,
+
+    /// Creation order of the next new cookie.
+    next: usize,
+}
+
+/// A cookie in the jar, with the state RFC 6265 section 5.3 keeps for it.
+#[derive(Clone, Debug)]
+struct Stored {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    expiry: Option<DateTime<FixedOffset>>,
+    secure: bool,
+    http_only: bool,
+    created: usize,
+}
+
+/// What the jar says about one request.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Step {
+    /// Index of the entry in the log.
+    pub entry: usize,
+
+    /// The cookies the jar holds for the request, in the order a browser sends them.
+    pub expected: Vec<Cookie>,
+
+    /// The cookies the request was recorded with.
+    pub actual: Vec<Cookie>,
+}
+
+impl Step {
+    /// Expected cookies the request did not send with the expected value.
+    pub fn missing(&self) -> Vec<&Cookie> {
+        difference(&self.expected, &self.actual)
+    }
+
+    /// Cookies the request sent that the jar does not hold with that value.
+    pub fn unexpected(&self) -> Vec<&Cookie> {
+        difference(&self.actual, &self.expected)
+    }
+
+    /// Whether the request sent exactly the expected cookies, in any order.
+    pub fn matches(&self) -> bool {
+        self.missing().is_empty() && self.unexpected().is_empty()
+    }
+}
+
+impl fmt::Display for Step {
+    /// Writes the expected and recorded cookies as Cookie header values.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "$.entries[{}].request.cookies: expected \"{}\", sent \"{}\"", self.entry,
+               cookie::cookie_header(&self.expected), cookie::cookie_header(&self.actual))
+    }
+}
+
+impl Jar {
+    pub fn new() -> Jar {
+        Jar::default()
+    }
+
+    /// Stores `cookie`, set by a response to `url` at `now`, unless RFC 6265 says to ignore it.
+    /// A cookie that has already expired removes the cookie it replaces.
+    pub fn store(&mut self, url: &Url, cookie: &Cookie, now: DateTime<FixedOffset>) {
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return,
+        };
+        let (domain, host_only) = match cookie.domain {
+            Some(ref domain) if !domain.is_empty() => {
+                let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                if !domain_matches(url, &host, &domain) {
+                    return;
+                }
+                (domain, false)
+            }
+            _ => (host, true),
+        };
+        let path = match cookie.path {
+            Some(ref path) if path.starts_with('/') => path.clone(),
+            _ => default_path(url),
+        };
+        let expiry = match cookie.max_age {
+            Some(max_age) if max_age <= 0 => Some(now),
+            // Ages past the end of time never expire, as if clamped (RFC 6265, section 5.2.2).
+            Some(max_age) => {
+                Duration::try_seconds(max_age).and_then(|age| now.checked_add_signed(age))
+            }
+            None => cookie.expires.as_ref().and_then(|expires| {
+                DateTime::parse_from_rfc3339(expires).ok()
+            }),
+        };
+
+        let existing = self.cookies.iter().position(|stored| {
+            stored.name == cookie.name && stored.domain == domain && stored.path == path
+        });
+        let created = match existing {
+            Some(index) => self.cookies.remove(index).created,
+            None => {
+                self.next += 1;
+                self.next - 1
+            }
+        };
+        if expiry.is_some_and(|expiry| expiry <= now) {
+            return;
+        }
+        self.cookies.push(Stored {
+            name: cookie.name.clone(),
+            value: cookie.value.clone(),
+            domain,
+            host_only,
+            path,
+            expiry,
+            secure: cookie.secure == Some(true),
+            http_only: cookie.http_only == Some(true),
+            created,
+        });
+    }
+
+    /// The cookies a request to `url` at `now` carries: those whose domain and path match and
+    /// that have not expired, longest path first and then oldest first. Secure cookies are only
+    /// sent over HTTPS.
+    pub fn cookies_for(&self, url: &Url, now: DateTime<FixedOffset>) -> Vec<Cookie> {
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return Vec::new(),
+        };
+        let secure = url.scheme() == "https" || url.scheme() == "wss";
+        let mut cookies: Vec<&Stored> = self.cookies.iter()
+            .filter(|stored| {
+                if stored.host_only {
+                    host == stored.domain
+                } else {
+                    domain_matches(url, &host, &stored.domain)
+                }
+            })
+            .filter(|stored| path_matches(url.path(), &stored.path))
+            .filter(|stored| secure || !stored.secure)
+            .filter(|stored| stored.expiry.is_none_or(|expiry| expiry > now))
+            .collect();
+        cookies.sort_by_key(|stored| (usize::MAX - stored.path.len(), stored.created));
+        cookies.into_iter()
+            .map(|stored| {
+                let mut cookie = Cookie::new(stored.name.clone(), stored.value.clone(),
+                                             Some(stored.path.clone()),
+                                             Some(stored.domain.clone()), None,
+                                             Some(stored.http_only), Some(stored.secure), None);
+                cookie.expires = stored.expiry.map(|expiry| expiry.to_rfc3339());
+                cookie
+            })
+            .collect()
+    }
+}
+
+/// Replays the cookies of `log`, returning a step for every entry with a valid start time and
+/// URL, in the order the entries started.
+pub fn replay(log: &Log) -> Vec<Step> {
+    let mut entries: Vec<(usize, &Entry, DateTime<FixedOffset>, Url)> = log.entries.iter()
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            let started = entry.started()?;
+            let url = Url::parse(&entry.request.url).ok()?;
+            Some((index, entry, started, url))
+        })
+        .collect();
+    entries.sort_by_key(|&(_, _, started, _)| started);
+
+    let mut jar = Jar::new();
+    let mut steps = Vec::new();
+    for (index, entry, started, url) in entries {
+        steps.push(Step {
+            entry: index,
+            expected: jar.cookies_for(&url, started),
+            actual: sent_cookies(entry),
+        });
+        for cookie in set_cookies(entry) {
+            jar.store(&url, &cookie, started);
+        }
+    }
+    steps
+}
+
+/// The cookies a request sent: its cookie list, or its Cookie headers if the list is empty.
+fn sent_cookies(entry: &Entry) -> Vec<Cookie> {
+    let request = &entry.request;
+    if !request.cookies.is_empty() {
+        return request.cookies.clone();
+    }
+    request.headers.iter()
+        .filter(|header| header.name.eq_ignore_ascii_case("cookie"))
+        .flat_map(|header| cookie::parse_cookie_header(&header.value))
+        .collect()
+}
+
+/// The cookies a response set: its Set-Cookie headers, or its cookie list if it has none.
+///
+/// The headers come first since browsers record the attributes of `cookies` inconsistently.
+fn set_cookies(entry: &Entry) -> Vec<Cookie> {
+    let response = &entry.response;
+    let cookies: Vec<Cookie> = response.headers.iter()
+        .filter(|header| header.name.eq_ignore_ascii_case("set-cookie"))
+        .filter_map(|header| cookie::parse_set_cookie(&header.value))
+        .collect();
+    if cookies.is_empty() {
+        response.cookies.clone()
+    } else {
+        cookies
+    }
+}
+
+fn difference<'a>(cookies: &'a [Cookie], others: &[Cookie]) -> Vec<&'a Cookie> {
+    cookies.iter()
+        .filter(|cookie| {
+            !others.iter().any(|other| other.name == cookie.name && other.value == cookie.value)
+        })
+        .collect()
+}
+
+/// Whether `host` domain-matches `domain` (RFC 6265 section 5.1.3).
+fn domain_matches(url: &Url, host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    let is_name = matches!(url.host(), Some(Host::Domain(_)));
+    is_name && host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.')
+}
+
+/// Whether `path` path-matches `cookie_path` (RFC 6265 section 5.1.4).
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path ||
+        (path.starts_with(cookie_path) &&
+         (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The path a cookie without a Path attribute gets: the request path up to its last `/`.
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    match path.rfind('/') {
+        Some(index) if index > 0 => path[..index].to_string(),
+        _ => "/".to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use jar;
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Header;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(started: &str, url: &str, cookie: Option<&str>, set_cookies: &[&str]) -> Entry {
+        let request_headers = cookie.iter()
+            .map(|cookie| Header::new("Cookie".to_string(), cookie.to_string(), None))
+            .collect();
+        let response_headers = set_cookies.iter()
+            .map(|value| Header::new("Set-Cookie".to_string(), value.to_string(), None))
+            .collect();
+        Entry::new(
+            None,
+            started.to_string(),
+            Request::new("GET".to_string(), url.to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                         request_headers, Vec::new(), None, None, None, None),
+            Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                          response_headers,
+                          Content::new(0, None, "text/html".to_string(), None, None, None),
+                          "".to_string(), None, None, None),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable, None),
+            None,
+            None,
+            None
+        )
+    }
+
+    fn names(cookies: &[::Cookie]) -> Vec<String> {
+        cookies.iter().map(|cookie| format!("{}={}", cookie.name, cookie.value)).collect()
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut log = Log::new(None, None);
+        log.add_entry(entry("2009-04-16T12:07:20.000Z", "https://www.example.com/login", None,
+                            &["session=1; Path=/; Secure; HttpOnly",
+                              "theme=dark; Domain=example.com; Path=/",
+                              "login=1",
+                              "short=1; Path=/; Max-Age=5",
+                              "other=1; Domain=example.org",
+                              "forever=1; Path=/; Max-Age=9223372036854775807"]));
+        // Listed out of order: this request starts last.
+        log.add_entry(entry("2009-04-16T12:08:00.000Z", "http://api.example.com/v1",
+                            Some("theme=dark; short=1"), &[]));
+        log.add_entry(entry("2009-04-16T12:07:21.000Z", "https://www.example.com/account",
+                            Some("theme=dark; session=1"), &["session=2; Path=/; Secure"]));
+        log.add_entry(entry("2009-04-16T12:07:22.000Z", "https://www.example.com/login/next",
+                            Some("session=2"), &[]));
+
+        let steps = jar::replay(&log);
+        assert_eq!(steps.iter().map(|step| step.entry).collect::<Vec<_>>(), vec![0, 2, 3, 1]);
+        assert!(steps[0].expected.is_empty());
+
+        // The path of `login` defaults to "/", the directory of "/login".
+        assert_eq!(names(&steps[1].expected),
+                   vec!["session=1", "theme=dark", "login=1", "short=1", "forever=1"]);
+        assert!(steps[1].unexpected().is_empty());
+        assert_eq!(names(&steps[1].missing().into_iter().cloned().collect::<Vec<_>>()),
+                   vec!["login=1", "short=1", "forever=1"]);
+        assert!(!steps[1].matches());
+        assert_eq!(steps[1].to_string(),
+                   "$.entries[2].request.cookies: expected \"session=1; theme=dark; login=1; \
+                    short=1; forever=1\", sent \"theme=dark; session=1\"");
+
+        // The replaced session keeps its place.
+        assert_eq!(names(&steps[2].expected),
+                   vec!["session=2", "theme=dark", "login=1", "short=1", "forever=1"]);
+
+        // Expired, insecure and host-only cookies stay behind.
+        assert_eq!(names(&steps[3].expected), vec!["theme=dark"]);
+        assert_eq!(names(&steps[3].unexpected().into_iter().cloned().collect::<Vec<_>>()),
+                   vec!["short=1"]);
+    }
+
+    #[test]
+    fn test_deleted_cookie() {
+        let mut log = Log::new(None, None);
+        log.add_entry(entry("2009-04-16T12:07:20.000Z", "http://example.com/", None,
+                            &["a=1", "b=1"]));
+        log.add_entry(entry("2009-04-16T12:07:21.000Z", "http://example.com/logout",
+                            Some("a=1; b=1"),
+                            &["a=; Max-Age=0", "b=; Expires=Thu, 01 Jan 1970 00:00:00 GMT"]));
+        log.add_entry(entry("2009-04-16T12:07:22.000Z", "http://example.com/", None, &[]));
+
+        let steps = jar::replay(&log);
+        assert!(steps[1].matches());
+        assert!(steps[2].expected.is_empty());
+        assert!(steps[2].matches());
+    }
+}
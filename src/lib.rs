@@ -2,12 +2,53 @@
 ///
 /// [1]: http://www.softwareishard.com/blog/har-12-spec/
 
+extern crate base64;
+extern crate chrono;
+extern crate flate2;
+extern crate hmac;
+extern crate regex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
-
+extern crate sha2;
+extern crate url;
+extern crate zip;
+extern crate zstd;
+
+pub mod body;
+pub mod cassette;
+pub mod convert;
+pub mod cookie;
+pub mod dedup;
+pub mod detect;
+pub mod diff;
+pub mod file;
+pub mod filter;
+pub mod form;
+pub mod headers;
+mod http;
+pub mod jar;
+pub mod matcher;
+pub mod merge;
+pub mod pseudonymize;
+pub mod query;
+pub mod redact;
+pub mod report;
+pub mod serve;
+pub mod sidecar;
+pub mod size;
+pub mod split;
+pub mod stats;
+pub mod validate;
+pub mod waterfall;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, FixedOffset};
 use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
 
 const HAR_VERSION: &'static str = "1.2";
 const HAR_CREATOR_NAME: &'static str = "Rust-HAR";
@@ -16,7 +57,7 @@ const HAR_CREATOR_VERSION: &'static str = "0.0.4";
 /// This object represents the root of the exported data.
 ///
 /// This object MUST be present and its name MUST be "log".
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Log {
     /// Version number of the format.
@@ -26,17 +67,24 @@ pub struct Log {
     creator: Creator,
 
     /// Name and version info of used browser.
+    #[serde(skip_serializing_if = "Option::is_none")]
     browser: Option<Browser>,
 
     /// List of all exported (tracked) pages.
     /// Leave out this field if the application does not support grouping by pages.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pages: Option<Vec<Page>>,
 
     /// List of all exported (tracked) requests.
     entries: Vec<Entry>,
 
     /// A comment provided by the user or the application.
-    comment: Option<String>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+
+    /// (custom) Response bodies shared by several entries, by the SHA-256 hash of their bytes.
+    #[serde(rename = "_bodies", default, skip_serializing_if = "BTreeMap::is_empty")]
+    bodies: BTreeMap<String, Arc<SharedBody>>
 }
 
 impl Log {
@@ -51,7 +99,8 @@ impl Log {
             browser: browser,
             pages: None,
             entries: Vec::new(),
-            comment: comment
+            comment: comment,
+            bodies: BTreeMap::new()
         }
     }
 
@@ -63,16 +112,47 @@ impl Log {
     }
 
     pub fn add_entry(&mut self, entry: Entry) {
+        self.adopt_body(&entry.response.content);
         self.entries.push(entry);
     }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// A copy of this log's metadata holding `entries` and only the pages they refer to.
+    fn subset(&self, entries: Vec<&Entry>) -> Log {
+        let pages = self.pages.as_ref().map(|pages| {
+            pages.iter()
+                .filter(|page| {
+                    entries.iter().any(|entry| entry.pageref.as_ref() == Some(&page.id))
+                })
+                .cloned()
+                .collect()
+        });
+        let mut log = Log {
+            version: self.version.clone(),
+            creator: self.creator.clone(),
+            browser: self.browser.clone(),
+            pages,
+            entries: Vec::new(),
+            comment: self.comment.clone(),
+            bodies: BTreeMap::new(),
+        };
+        for entry in entries {
+            log.add_entry(entry.clone());
+        }
+        log
+    }
 }
 
 /// This object contains information about the log creator application.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Creator {
     name: String,
     version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -87,11 +167,12 @@ impl Creator {
 }
 
 /// This object contains information about the browser that created the log.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Browser {
     name: String,
     version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -106,7 +187,7 @@ impl Browser {
 }
 
 /// This object represents list of exported pages.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Page {
     /// Date and time stamp for the beginning of the page load
@@ -119,6 +200,7 @@ pub struct Page {
     /// Detailed timing info about page load.
     page_timings: PageTimings,
     /// A comment provided by the user or the application.
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -136,13 +218,18 @@ impl Page {
             comment: comment
         }
     }
+
+    /// The start of the page load, if `started_date_time` is a valid ISO 8601 (RFC 3339) date.
+    pub fn started(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.started_date_time).ok()
+    }
 }
 
 /// This object describes timings for various events (states) fired during the page load.
 ///
 /// All times are specified in milliseconds.
 /// If a time info is not available appropriate field is set to -1.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PageTimings {
     /// Content of the page loaded.
@@ -156,6 +243,7 @@ pub struct PageTimings {
     on_load: OptionalTiming,
 
     /// A comment provided by the user or the application.
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -177,11 +265,12 @@ impl PageTimings {
 /// startedDateTime (starting from the oldest) is preferred way how to export data since it can
 /// make importing faster. However the reader application should always make sure the array is
 /// sorted (if required for the import).
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Entry {
     /// Reference to the parent page (unique).
     /// Leave out this field if the application does not support grouping by pages.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pageref: Option<String>,
 
     /// Date and time stamp of the request start (ISO 8601 YYYY-MM-DDThh:mm:ss.sTZD).
@@ -204,6 +293,7 @@ pub struct Entry {
     timings: Timing,
 
     /// IP address of the server that was connected (result of DNS resolution).
+    #[serde(skip_serializing_if = "Option::is_none")]
     server_ip_address: Option<String>,
 
     /// Unique ID of the parent TCP/IP connection, can be the client port number.
@@ -212,10 +302,16 @@ pub struct Entry {
     /// shared for more connections. If the port isn't available for the application, any other
     /// unique connection ID can be used instead (e.g. connection index). Leave out this field if
     /// the application doesn't support this info.
+    #[serde(skip_serializing_if = "Option::is_none")]
     connection: Option<String>,
 
     /// A comment provided by the user or the application.
-    comment: Option<String>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+
+    /// (custom) Name of the log this entry was merged from.
+    #[serde(rename = "_source", skip_serializing_if = "Option::is_none")]
+    source: Option<String>
 }
 
 impl Entry {
@@ -239,13 +335,24 @@ impl Entry {
             timings: timings,
             server_ip_address: server_ip_address,
             connection: connection,
-            comment: comment
+            comment: comment,
+            source: None
         }
     }
+
+    /// The start of the request, if `started_date_time` is a valid ISO 8601 (RFC 3339) date.
+    pub fn started(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.started_date_time).ok()
+    }
+
+    /// Name of the log this entry was merged from, if it came from a merge.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
 }
 
 /// This object contains detailed info about performed request.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Request {
     /// Request method (GET, POST, ...).
@@ -267,18 +374,22 @@ pub struct Request {
     query_string: Vec<QueryStringPair>,
 
     /// Posted data info.
+    #[serde(skip_serializing_if = "Option::is_none")]
     post_data: Option<PostData>,
 
     /// Total number of bytes from the start of the HTTP request message until (and including)
     /// the double CRLF before the body.
     /// Set to -1 if the info is not available.
+    #[serde(default, serialize_with = "serialize_size", deserialize_with = "deserialize_size")]
     headers_size: Option<i32>,
 
     /// Size of the request body (POST data payload) in bytes.
     /// Set to -1 if the info is not available.
+    #[serde(default, serialize_with = "serialize_size", deserialize_with = "deserialize_size")]
     body_size: Option<i32>,
 
     /// A comment provided by the user or the application.
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -311,7 +422,7 @@ impl Request {
 }
 
 /// This object contains detailed info about the response.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Response {
     /// Response status.
@@ -342,14 +453,17 @@ pub struct Response {
     /// The size of received response-headers is computed only from headers that are really
     /// received from the server. Additional headers appended by the browser are not included in
     /// this number, but they appear in the list of header objects.
+    #[serde(default, serialize_with = "serialize_size", deserialize_with = "deserialize_size")]
     headers_size: Option<i32>,
 
     /// Size of the received response body in bytes.
     /// Set to zero in case of responses coming from the cache (304).
     /// Set to -1 if the info is not available.
+    #[serde(default, serialize_with = "serialize_size", deserialize_with = "deserialize_size")]
     body_size: Option<i32>,
 
     /// A comment provided by the user or the application.
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -383,7 +497,7 @@ impl Response {
 
 
 /// This object contains list of all cookies (used in <request> and <response> objects).
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Cookie {
     /// The name of the cookie.
@@ -393,22 +507,36 @@ pub struct Cookie {
     value: String,
 
     /// The path pertaining to the cookie.
+    #[serde(skip_serializing_if = "Option::is_none")]
     path: Option<String>,
 
     /// The host of the cookie.
+    #[serde(skip_serializing_if = "Option::is_none")]
     domain: Option<String>,
 
     /// Cookie expiration time. (ISO 8601).
+    #[serde(skip_serializing_if = "Option::is_none")]
     expires: Option<String>,
 
     /// Set to true if the cookie is HTTP only, false otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
     http_only: Option<bool>,
 
     /// True if the cookie was transmitted over ssl, false otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
     secure: Option<bool>,
 
     /// A comment provided by the user or the application.
-    comment: Option<String>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+
+    /// (custom) The SameSite attribute: "Strict", "Lax" or "None".
+    #[serde(rename = "_sameSite", skip_serializing_if = "Option::is_none")]
+    same_site: Option<String>,
+
+    /// (custom) The Max-Age attribute, in seconds.
+    #[serde(rename = "_maxAge", skip_serializing_if = "Option::is_none")]
+    max_age: Option<i64>
 }
 
 impl Cookie {
@@ -430,18 +558,21 @@ impl Cookie {
             expires: expires,
             http_only: http_only,
             secure: secure,
-            comment: comment
+            comment: comment,
+            same_site: None,
+            max_age: None
         }
     }
 }
 
 
 /// This object contains list of all headers (used in <request> and <response> objects).
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Header {
     name: String,
     value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -462,11 +593,12 @@ impl Header {
 /// This object contains list of all parameters & values parsed from a query string, if any
 /// (embedded in <request> object).
 /// HAR format expects NVP (name-value pairs) formatting of the query string.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct QueryStringPair {
     name: String,
     value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -486,7 +618,7 @@ impl QueryStringPair {
 
 /// This object describes posted data, if any (embedded in <request> object).
 /// Note that text and params fields are mutually exclusive.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PostData {
     /// Mime type of posted data.
@@ -499,7 +631,12 @@ pub struct PostData {
     text: String,
 
     /// A comment provided by the user or the application.
-    comment: Option<String>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+
+    /// (custom) Name of the sidecar file the text was extracted to.
+    #[serde(rename = "_file", skip_serializing_if = "Option::is_none")]
+    file: Option<String>
 }
 
 impl PostData {
@@ -513,29 +650,38 @@ impl PostData {
             mime_type: mime_type,
             params: params,
             text: text,
-            comment: comment
+            comment: comment,
+            file: None
         }
     }
 }
 
 /// List of posted parameters, if any (embedded in <postData> object).
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Param {
     /// name of a posted parameter.
     name: String,
 
     /// value of a posted parameter or content of a posted file.
+    #[serde(skip_serializing_if = "Option::is_none")]
     value: Option<String>,
 
     /// name of a posted file.
+    #[serde(skip_serializing_if = "Option::is_none")]
     file_name: Option<String>,
 
     /// content type of a posted file.
+    #[serde(skip_serializing_if = "Option::is_none")]
     content_type: Option<String>,
 
     /// A comment provided by the user or the application.
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>,
+
+    /// (custom) Encoding used for the value, "base64" for binary file contents.
+    #[serde(rename = "_encoding", skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
 }
 
 impl Param {
@@ -551,7 +697,8 @@ impl Param {
             value: value,
             file_name: file_name,
             content_type: content_type,
-            comment: comment
+            comment: comment,
+            encoding: None
         }
     }
 }
@@ -562,7 +709,7 @@ impl Param {
 /// trans-coded from its original character set into UTF-8. Additionally, it can be encoded using
 /// e.g. base64. Ideally, the application should be able to unencode a base64 blob and get a
 /// byte-for-byte identical resource to what the browser operated on.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Content {
     /// Length of the returned content in bytes.
@@ -571,6 +718,7 @@ pub struct Content {
     size: i32,
 
     /// Number of bytes saved. Leave out this field if the information is not available.
+    #[serde(skip_serializing_if = "Option::is_none")]
     compression: Option<i32>,
 
     /// MIME type of the response text (value of the Content-Type response header).
@@ -582,15 +730,42 @@ pub struct Content {
     /// The text field is either HTTP decoded text or a encoded (e.g. "base64") representation of
     /// the response body.
     /// Leave out this field if the information is not available.
+    #[serde(skip_serializing_if = "Option::is_none")]
     text: Option<String>,
 
     /// Encoding used for response text field e.g "base64".
     /// Leave out this field if the text field is HTTP decoded (decompressed & unchunked),
     /// than trans-coded from its original character set into UTF-8.
+    #[serde(skip_serializing_if = "Option::is_none")]
     encoding: Option<String>,
 
     /// A comment provided by the user or the application.
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>,
+
+    /// (custom) Name of the sidecar file the text was extracted to.
+    #[serde(rename = "_file", skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+
+    /// (custom) Hash of the body in the log's `_bodies` table this content shares.
+    #[serde(rename = "_body", skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+
+    /// The shared body `body` refers to, once linked to the log's table.
+    #[serde(skip_serializing, skip_deserializing)]
+    shared: Option<Arc<SharedBody>>,
+}
+
+/// (custom) A response body stored once in the log and shared by every content with the same
+/// bytes.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SharedBody {
+    /// The body text, as in `content.text`.
+    text: String,
+
+    /// Encoding used for the text, as in `content.encoding`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
 }
 
 impl Content {
@@ -608,25 +783,29 @@ impl Content {
             mime_type: mime_type,
             text: text,
             encoding: encoding,
-            comment: comment
+            comment: comment,
+            file: None,
+            body: None,
+            shared: None
         }
     }
 }
 
 /// This objects contains info about a request coming from browser cache.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Cache {
     /// State of a cache entry before the request.
     /// Leave out this field if the information is not available.
-    #[serde(default = "CacheState::unknown")]
+    #[serde(default = "CacheState::unknown", skip_serializing_if = "CacheState::is_unknown")]
     before_request: CacheState,
 
     /// State of a cache entry after the request.
     /// Leave out this field if the information is not available.
-    #[serde(default = "CacheState::unknown")]
+    #[serde(default = "CacheState::unknown", skip_serializing_if = "CacheState::is_unknown")]
     after_request: CacheState,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -648,7 +827,7 @@ impl Cache {
 ///
 /// Can be Absent, Present, or Unknown. When serialized, these result in (respectively) `null`, a
 /// CacheEntry value, or no object.
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 #[serde(untagged)]
 pub enum CacheState {
@@ -659,13 +838,16 @@ pub enum CacheState {
 
 impl CacheState {
     fn unknown() -> Self { CacheState::Unknown }
+
+    fn is_unknown(&self) -> bool { *self == CacheState::Unknown }
 }
 
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct CacheEntry {
     /// Expiration time of the cache entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
     expires: Option<String>,
 
     /// The last time the cache entry was opened.
@@ -678,6 +860,7 @@ pub struct CacheEntry {
     hit_count: i32,
 
     /// (new in 1.2) A comment provided by the user or the application.
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>,
 }
 
@@ -699,17 +882,52 @@ impl CacheEntry {
     }
 }
 
+/// Writes a missing `headersSize` or `bodySize` as -1, the HAR value for "not available", since
+/// both fields are required.
+fn serialize_size<S>(size: &Option<i32>, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+{
+    serializer.serialize_i32(size.unwrap_or(-1))
+}
+
+/// Reads a `headersSize` or `bodySize`, treating -1 and null as missing.
+fn deserialize_size<D>(deserializer: D) -> Result<Option<i32>, D::Error>
+    where D: Deserializer
+{
+    let size: Option<i32> = Deserialize::deserialize(deserializer)?;
+    Ok(size.filter(|&size| size != -1))
+}
+
 /// A timing value which may be absent or present
 ///
 /// Defaults to -1 in the absent case.
-#[derive(Serialize, PartialEq, Debug)]
-#[serde(rename_all = "camelCase")]
-#[serde(untagged)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum OptionalTiming {
     TimedContent(u32),
     NotApplicable
 }
 
+impl OptionalTiming {
+    /// The time in milliseconds, or `None` if the timing does not apply.
+    pub fn millis(&self) -> Option<u32> {
+        match *self {
+            OptionalTiming::TimedContent(time) => Some(time),
+            OptionalTiming::NotApplicable => None,
+        }
+    }
+}
+
+impl Serialize for OptionalTiming {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        match *self {
+            OptionalTiming::TimedContent(time) => serializer.serialize_u32(time),
+            OptionalTiming::NotApplicable => serializer.serialize_i32(-1),
+        }
+    }
+}
+
 impl Deserialize for OptionalTiming {
     fn deserialize<D>(deserializer: D) -> Result<OptionalTiming, D::Error>
         where D: Deserializer
@@ -742,7 +960,7 @@ impl Deserialize for OptionalTiming {
 /// entry.time == entry.timings.blocked + entry.timings.dns +
 ///     entry.timings.connect + entry.timings.send + entry.timings.wait +
 ///         entry.timings.receive;
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Timing {
     /// Time spent in a queue waiting for a network connection.
@@ -773,6 +991,7 @@ pub struct Timing {
     ssl: OptionalTiming,
 
     /// (new in 1.2) - A comment provided by the user or the application.
+    #[serde(skip_serializing_if = "Option::is_none")]
     comment: Option<String>
 }
 
@@ -798,6 +1017,18 @@ impl Timing {
             comment: comment
         }
     }
+
+    /// Total elapsed time of the request in milliseconds, i.e. the value of `entry.time`.
+    ///
+    /// This is the sum of all applicable timings. `ssl` is not added separately since it is
+    /// already included in `connect`.
+    /// Saturates at `u32::MAX` rather than overflowing.
+    pub fn total(&self) -> u32 {
+        [self.blocked.millis().unwrap_or(0), self.dns.millis().unwrap_or(0),
+         self.connect.millis().unwrap_or(0), self.send, self.wait, self.receive]
+            .iter()
+            .fold(0u32, |total, &millis| total.saturating_add(millis))
+    }
 }
 
 
@@ -980,6 +1211,30 @@ mod test {
         assert_eq!( log_from_str, log );
     }
 
+    #[test]
+    fn test_no_optional_omitted() {
+        let log = Log::new(None, None);
+        let log_json = serde_json::to_string(&log).unwrap();
+        assert_eq!(log_json,
+                   "{\"version\":\"1.2\",\"creator\":{\"name\":\"Rust-HAR\",\
+                    \"version\":\"0.0.4\"},\"entries\":[]}");
+
+        let log_json = "{
+                            \"version\": \"1.2\",
+                            \"creator\": {
+                                \"name\": \"Rust-HAR\",
+                                \"version\": \"0.0.4\",
+                                \"comment\": null
+                            },
+                            \"browser\": null,
+                            \"pages\": null,
+                            \"entries\": [],
+                            \"comment\": null
+                        }";
+        let log_from_str: Log = serde_json::from_str(log_json).unwrap();
+        assert_eq!( log_from_str, log );
+    }
+
     #[test]
     fn test_creator() {
         let creator = Creator::new(
@@ -1474,6 +1729,25 @@ mod test {
         assert_eq!(response_from_str, response );
     }
 
+    #[test]
+    fn test_unknown_sizes() {
+        let request = Request::new("GET".to_string(), "http://www.example.com/".to_string(),
+                                   "HTTP/1.1".to_string(), Vec::new(), Vec::new(), Vec::new(),
+                                   None, None, Some(0), None);
+        let request_json = serde_json::to_string(&request).unwrap();
+        assert!(request_json.contains("\"headersSize\":-1,\"bodySize\":0"));
+        assert_eq!(serde_json::from_str::<Request>(&request_json).unwrap(), request);
+
+        let response = Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                                     Vec::new(),
+                                     Content::new(0, None, "text/html".to_string(), None, None,
+                                                  None),
+                                     "".to_string(), None, None, None);
+        let response_json = serde_json::to_string(&response).unwrap();
+        assert!(response_json.contains("\"headersSize\":-1,\"bodySize\":-1"));
+        assert_eq!(serde_json::from_str::<Response>(&response_json).unwrap(), response);
+    }
+
     #[test]
     fn test_cookie() {
         let cookie = Cookie::new(
@@ -1823,6 +2097,17 @@ mod test {
         assert_eq!(timing_from_str, timing );
     }
 
+    #[test]
+    fn test_timing_total() {
+        let timing = Timing::new(TimedContent(1), NotApplicable, TimedContent(20), 4, 5, 6,
+                                 TimedContent(15), None);
+        assert_eq!(timing.total(), 36);
+
+        let timing = Timing::new(TimedContent(u32::MAX), NotApplicable, NotApplicable, 4,
+                                 u32::MAX, 6, NotApplicable, None);
+        assert_eq!(timing.total(), u32::MAX);
+    }
+
     #[test]
     fn test_timing_no_optional() {
         let timing = Timing::new(
@@ -0,0 +1,528 @@
+//! Matching incoming requests against recorded entries.
+//!
+//! A `Matcher` decides which recorded `Request`s are equivalent to an incoming one. By default
+//! two requests match when their method, path, query and body agree; headers and cookies are
+//! only compared once enabled, and each comparison can be relaxed for volatile values.
+
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+
+use regex::{self, Regex};
+use serde_json;
+use url::Url;
+
+use cookie;
+use Entry;
+use Request;
+
+/// Request headers that vary between otherwise identical requests. They are ignored when
+/// headers are compared.
+const VOLATILE_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "cookie",
+    "date",
+    "host",
+    "if-modified-since",
+    "if-none-match",
+    "keep-alive",
+    "user-agent",
+];
+
+/// Decides whether an incoming request is a replay of a recorded one.
+pub struct Matcher {
+    match_origin: bool,
+    ignore_query_order: bool,
+    ignored_query_params: HashSet<String>,
+    match_headers: bool,
+    ignored_headers: HashSet<String>,
+    match_cookies: bool,
+    ignored_cookies: HashSet<String>,
+    match_body: bool,
+    url_rules: Vec<(Regex, String)>,
+}
+
+impl Default for Matcher {
+    fn default() -> Matcher {
+        Matcher::new()
+    }
+}
+
+impl Matcher {
+    /// Creates a matcher comparing method, path, query (in order) and body.
+    pub fn new() -> Matcher {
+        Matcher {
+            match_origin: false,
+            ignore_query_order: false,
+            ignored_query_params: HashSet::new(),
+            match_headers: false,
+            ignored_headers: VOLATILE_HEADERS.iter().map(|name| name.to_string()).collect(),
+            match_cookies: false,
+            ignored_cookies: HashSet::new(),
+            match_body: true,
+            url_rules: Vec::new(),
+        }
+    }
+
+    /// Also compare scheme, host and port. Off by default, so that a capture of any site can be
+    /// replayed from a local server.
+    pub fn match_origin(mut self, enabled: bool) -> Matcher {
+        self.match_origin = enabled;
+        self
+    }
+
+    /// Treat query strings that differ only in parameter order as equal.
+    pub fn ignore_query_order(mut self, enabled: bool) -> Matcher {
+        self.ignore_query_order = enabled;
+        self
+    }
+
+    /// Leave the query parameter `name` out of the comparison.
+    pub fn ignore_query_param(mut self, name: &str) -> Matcher {
+        self.ignored_query_params.insert(name.to_string());
+        self
+    }
+
+    /// Compare request headers, except for volatile ones such as `Date` and `User-Agent`.
+    pub fn match_headers(mut self, enabled: bool) -> Matcher {
+        self.match_headers = enabled;
+        self
+    }
+
+    /// Leave the header `name` (case-insensitive) out of the comparison.
+    pub fn ignore_header(mut self, name: &str) -> Matcher {
+        self.ignored_headers.insert(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Compare request cookies.
+    pub fn match_cookies(mut self, enabled: bool) -> Matcher {
+        self.match_cookies = enabled;
+        self
+    }
+
+    /// Leave the cookie `name` out of the comparison.
+    pub fn ignore_cookie(mut self, name: &str) -> Matcher {
+        self.ignored_cookies.insert(name.to_string());
+        self
+    }
+
+    /// Compare request bodies. Bodies with a JSON MIME type are compared as JSON values, so
+    /// formatting and key order do not matter.
+    pub fn match_body(mut self, enabled: bool) -> Matcher {
+        self.match_body = enabled;
+        self
+    }
+
+    /// Rewrite both URLs with `pattern` and `replacement` (as in `Regex::replace_all`) before
+    /// comparing them. Rules are applied in the order they were added.
+    pub fn rewrite_url(mut self,
+                       pattern: &str,
+                       replacement: &str) -> Result<Matcher, regex::Error> {
+        self.url_rules.push((Regex::new(pattern)?, replacement.to_string()));
+        Ok(self)
+    }
+
+    /// Compares a recorded request with an incoming one, returning every difference found.
+    pub fn compare(&self, recorded: &Request, request: &Request) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+
+        if !recorded.method.eq_ignore_ascii_case(&request.method) {
+            mismatches.push(Mismatch::Method(recorded.method.clone(), request.method.clone()));
+        }
+
+        let recorded_url = self.normalize_url(&recorded.url);
+        let url = self.normalize_url(&request.url);
+        if recorded_url.location != url.location {
+            mismatches.push(Mismatch::Url(recorded_url.location.clone(), url.location.clone()));
+        }
+        if recorded_url.query != url.query {
+            mismatches.push(Mismatch::Query(query_text(&recorded_url.query),
+                                            query_text(&url.query)));
+        }
+
+        if self.match_headers {
+            let recorded_headers = self.headers(recorded);
+            let headers = self.headers(request);
+            let mut names: Vec<&String> = recorded_headers.iter().chain(headers.iter())
+                .map(|(name, _)| name)
+                .collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                let values = |headers: &[(String, String)]| -> Vec<String> {
+                    headers.iter()
+                        .filter(|(header, _)| header == name)
+                        .map(|(_, value)| value.clone())
+                        .collect()
+                };
+                if values(&recorded_headers) != values(&headers) {
+                    mismatches.push(Mismatch::Header(name.clone()));
+                }
+            }
+        }
+
+        if self.match_cookies {
+            let recorded_cookies = self.cookies(recorded);
+            let cookies = self.cookies(request);
+            let mut names: Vec<&String> = recorded_cookies.iter().chain(cookies.iter())
+                .map(|(name, _)| name)
+                .collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                let value = |cookies: &[(String, String)]| {
+                    cookies.iter().find(|(cookie, _)| cookie == name).map(|(_, value)| value.clone())
+                };
+                if value(&recorded_cookies) != value(&cookies) {
+                    mismatches.push(Mismatch::Cookie(name.clone()));
+                }
+            }
+        }
+
+        if self.match_body && !bodies_equal(recorded, request) {
+            mismatches.push(Mismatch::Body);
+        }
+
+        mismatches
+    }
+
+    /// Returns the indices of all entries whose request matches `request`, in log order.
+    pub fn candidates(&self, request: &Request, entries: &[Entry]) -> Result<Vec<usize>, NoMatch> {
+        let mut matches = Vec::new();
+        let mut rejections = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            let mismatches = self.compare(&entry.request, request);
+            if mismatches.is_empty() {
+                matches.push(index);
+            } else {
+                rejections.push(Rejection { entry: index, mismatches });
+            }
+        }
+        if matches.is_empty() {
+            Err(NoMatch { rejections })
+        } else {
+            Ok(matches)
+        }
+    }
+
+    /// Returns the index of the entry answering the `occurrence`-th (zero based) repetition of
+    /// `request`.
+    ///
+    /// Repetitions beyond the number of recorded matches are answered by the last match.
+    pub fn find(&self,
+                request: &Request,
+                entries: &[Entry],
+                occurrence: usize) -> Result<usize, NoMatch> {
+        let candidates = self.candidates(request, entries)?;
+        Ok(candidates[occurrence.min(candidates.len() - 1)])
+    }
+
+    fn normalize_url(&self, url: &str) -> NormalizedUrl {
+        let mut url = url.to_string();
+        for (pattern, replacement) in &self.url_rules {
+            url = pattern.replace_all(&url, replacement.as_str()).into_owned();
+        }
+        let parsed = match Url::parse(&url) {
+            Ok(parsed) => parsed,
+            Err(_) => return NormalizedUrl { location: url, query: Vec::new() },
+        };
+        let location = if self.match_origin {
+            format!("{}{}", parsed.origin().ascii_serialization(), parsed.path())
+        } else {
+            parsed.path().to_string()
+        };
+        let mut query: Vec<(String, String)> = parsed.query_pairs()
+            .filter(|(name, _)| !self.ignored_query_params.contains(name.as_ref()))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        if self.ignore_query_order {
+            query.sort();
+        }
+        NormalizedUrl { location, query }
+    }
+
+    fn headers(&self, request: &Request) -> Vec<(String, String)> {
+        let mut headers: Vec<(String, String)> = request.headers
+            .iter()
+            .map(|header| (header.name.to_ascii_lowercase(), header.value.clone()))
+            .filter(|(name, _)| !self.ignored_headers.contains(name))
+            .collect();
+        // Header order is not significant between different header names.
+        headers.sort_by(|a, b| a.0.cmp(&b.0));
+        headers
+    }
+
+    fn cookies(&self, request: &Request) -> Vec<(String, String)> {
+        let mut cookies: Vec<(String, String)> = request.cookies
+            .iter()
+            .map(|cookie| (cookie.name.clone(), cookie.value.clone()))
+            .collect();
+        if cookies.is_empty() {
+            for header in request.headers.iter().filter(|h| h.name.eq_ignore_ascii_case("cookie")) {
+                cookies.extend(cookie::parse_cookie_header(&header.value)
+                    .into_iter()
+                    .map(|cookie| (cookie.name, cookie.value)));
+            }
+        }
+        cookies.retain(|(name, _)| !self.ignored_cookies.contains(name));
+        cookies
+    }
+}
+
+struct NormalizedUrl {
+    location: String,
+    query: Vec<(String, String)>,
+}
+
+fn query_text(query: &[(String, String)]) -> String {
+    query.iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn body_text(request: &Request) -> &str {
+    request.post_data.as_ref().map_or("", |data| data.text.as_str())
+}
+
+fn bodies_equal(recorded: &Request, request: &Request) -> bool {
+    let is_json = |request: &Request| {
+        request.post_data.as_ref().is_some_and(|data| data.mime_type.contains("json"))
+    };
+    if is_json(recorded) || is_json(request) {
+        let recorded_json = serde_json::from_str::<serde_json::Value>(body_text(recorded));
+        let json = serde_json::from_str::<serde_json::Value>(body_text(request));
+        if let (Ok(recorded_json), Ok(json)) = (recorded_json, json) {
+            return recorded_json == json;
+        }
+    }
+    body_text(recorded) == body_text(request)
+}
+
+/// One way in which a recorded request differs from an incoming one.
+#[derive(PartialEq, Debug)]
+pub enum Mismatch {
+    /// Recorded and incoming methods.
+    Method(String, String),
+
+    /// Recorded and incoming URLs, without their query strings.
+    Url(String, String),
+
+    /// Recorded and incoming query strings, after normalization.
+    Query(String, String),
+
+    /// The values of this (lower-cased) header differ.
+    Header(String),
+
+    /// The values of this cookie differ.
+    Cookie(String),
+
+    /// The bodies differ.
+    Body,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mismatch::Method(ref recorded, ref actual) => {
+                write!(f, "method {} != {}", recorded, actual)
+            }
+            Mismatch::Url(ref recorded, ref actual) => write!(f, "url {} != {}", recorded, actual),
+            Mismatch::Query(ref recorded, ref actual) => {
+                write!(f, "query \"{}\" != \"{}\"", recorded, actual)
+            }
+            Mismatch::Header(ref name) => write!(f, "header {} differs", name),
+            Mismatch::Cookie(ref name) => write!(f, "cookie {} differs", name),
+            Mismatch::Body => write!(f, "body differs"),
+        }
+    }
+}
+
+/// Why a recorded entry was not a match.
+#[derive(PartialEq, Debug)]
+pub struct Rejection {
+    /// Index of the entry in the log.
+    pub entry: usize,
+
+    /// Every difference between the entry's request and the incoming one.
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// No recorded entry matched a request.
+#[derive(PartialEq, Debug)]
+pub struct NoMatch {
+    /// One rejection per entry in the log.
+    pub rejections: Vec<Rejection>,
+}
+
+impl fmt::Display for NoMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.rejections.is_empty() {
+            return write!(f, "no recorded entries");
+        }
+        write!(f, "no recorded entry matches:")?;
+        for rejection in &self.rejections {
+            let reasons: Vec<String> = rejection.mismatches.iter().map(|m| m.to_string()).collect();
+            write!(f, "\n  entry {}: {}", rejection.entry, reasons.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for NoMatch {}
+
+
+#[cfg(test)]
+mod test {
+
+    use matcher::{Matcher, Mismatch};
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Header;
+    use OptionalTiming::NotApplicable;
+    use PostData;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn request(method: &str, url: &str, headers: Vec<(&str, &str)>, body: Option<(&str, &str)>)
+               -> Request {
+        Request::new(
+            method.to_string(),
+            url.to_string(),
+            "HTTP/1.1".to_string(),
+            Vec::new(),
+            headers.into_iter()
+                .map(|(name, value)| Header::new(name.to_string(), value.to_string(), None))
+                .collect(),
+            Vec::new(),
+            body.map(|(mime_type, text)| {
+                PostData::new(mime_type.to_string(), Vec::new(), text.to_string(), None)
+            }),
+            None,
+            None,
+            None
+        )
+    }
+
+    fn entry(request: Request) -> Entry {
+        Entry::new(
+            None,
+            "2009-04-16T12:07:23.596Z".to_string(),
+            request,
+            Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(), Vec::new(),
+                          Content::new(0, None, "text/plain".to_string(), None, None, None),
+                          "".to_string(), None, None, None),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable, None),
+            None,
+            None,
+            None
+        )
+    }
+
+    #[test]
+    fn test_match_ignores_origin() {
+        let recorded = request("GET", "https://example.com/a?x=1", vec![], None);
+        let incoming = request("get", "http://localhost:8080/a?x=1", vec![], None);
+        assert!(Matcher::new().compare(&recorded, &incoming).is_empty());
+        assert_eq!(Matcher::new().match_origin(true).compare(&recorded, &incoming),
+                   vec![Mismatch::Url("https://example.com/a".to_string(),
+                                      "http://localhost:8080/a".to_string())]);
+    }
+
+    #[test]
+    fn test_match_query_order() {
+        let recorded = request("GET", "http://example.com/a?x=1&y=2&ts=5", vec![], None);
+        let incoming = request("GET", "http://example.com/a?y=2&x=1&ts=6", vec![], None);
+        assert_eq!(Matcher::new().ignore_query_param("ts").compare(&recorded, &incoming),
+                   vec![Mismatch::Query("x=1&y=2".to_string(), "y=2&x=1".to_string())]);
+        let matcher = Matcher::new().ignore_query_order(true).ignore_query_param("ts");
+        assert!(matcher.compare(&recorded, &incoming).is_empty());
+    }
+
+    #[test]
+    fn test_match_headers() {
+        let recorded = request("GET", "http://example.com/", vec![("Accept", "text/html"),
+                                                                  ("Date", "yesterday"),
+                                                                  ("X-Trace", "1")], None);
+        let incoming = request("GET", "http://example.com/", vec![("accept", "text/html"),
+                                                                  ("date", "today"),
+                                                                  ("x-trace", "2")], None);
+        assert!(Matcher::new().compare(&recorded, &incoming).is_empty());
+        assert_eq!(Matcher::new().match_headers(true).compare(&recorded, &incoming),
+                   vec![Mismatch::Header("x-trace".to_string())]);
+        let matcher = Matcher::new().match_headers(true).ignore_header("X-Trace");
+        assert!(matcher.compare(&recorded, &incoming).is_empty());
+    }
+
+    #[test]
+    fn test_match_cookies() {
+        let recorded = request("GET", "http://example.com/",
+                               vec![("Cookie", "session=abc; csrf=1")], None);
+        let incoming = request("GET", "http://example.com/",
+                               vec![("Cookie", "csrf=2; session=abc")], None);
+        assert_eq!(Matcher::new().match_cookies(true).compare(&recorded, &incoming),
+                   vec![Mismatch::Cookie("csrf".to_string())]);
+        let matcher = Matcher::new().match_cookies(true).ignore_cookie("csrf");
+        assert!(matcher.compare(&recorded, &incoming).is_empty());
+    }
+
+    #[test]
+    fn test_match_json_body() {
+        let recorded = request("POST", "http://example.com/api",
+                               vec![], Some(("application/json", "{\"a\": 1, \"b\": [1, 2]}")));
+        let incoming = request("POST", "http://example.com/api",
+                               vec![], Some(("application/json", "{\"b\":[1,2],\"a\":1}")));
+        let different = request("POST", "http://example.com/api",
+                                vec![], Some(("application/json", "{\"b\":[2,1],\"a\":1}")));
+        assert!(Matcher::new().compare(&recorded, &incoming).is_empty());
+        assert_eq!(Matcher::new().compare(&recorded, &different), vec![Mismatch::Body]);
+        assert!(Matcher::new().match_body(false).compare(&recorded, &different).is_empty());
+    }
+
+    #[test]
+    fn test_match_url_rules() {
+        let recorded = request("GET", "http://example.com/users/123/profile", vec![], None);
+        let incoming = request("GET", "http://example.com/users/456/profile", vec![], None);
+        let matcher = Matcher::new().rewrite_url(r"/users/\d+/", "/users/ID/").unwrap();
+        assert!(matcher.compare(&recorded, &incoming).is_empty());
+        assert!(Matcher::new().rewrite_url("(", "").is_err());
+    }
+
+    #[test]
+    fn test_find_occurrence() {
+        let entries = vec![
+            entry(request("GET", "http://example.com/poll", vec![], None)),
+            entry(request("GET", "http://example.com/other", vec![], None)),
+            entry(request("GET", "http://example.com/poll", vec![], None)),
+        ];
+        let incoming = request("GET", "http://localhost/poll", vec![], None);
+        let matcher = Matcher::new();
+        assert_eq!(matcher.candidates(&incoming, &entries), Ok(vec![0, 2]));
+        assert_eq!(matcher.find(&incoming, &entries, 0), Ok(0));
+        assert_eq!(matcher.find(&incoming, &entries, 1), Ok(2));
+        assert_eq!(matcher.find(&incoming, &entries, 5), Ok(2));
+    }
+
+    #[test]
+    fn test_no_match_explains_rejections() {
+        let entries = vec![
+            entry(request("GET", "http://example.com/a", vec![], None)),
+            entry(request("POST", "http://example.com/b", vec![], None)),
+        ];
+        let incoming = request("GET", "http://example.com/b", vec![], None);
+        let no_match = Matcher::new().find(&incoming, &entries, 0).unwrap_err();
+        assert_eq!(no_match.rejections[0].mismatches,
+                   vec![Mismatch::Url("/a".to_string(), "/b".to_string())]);
+        assert_eq!(no_match.rejections[1].mismatches,
+                   vec![Mismatch::Method("POST".to_string(), "GET".to_string())]);
+        assert_eq!(no_match.to_string(),
+                   "no recorded entry matches:\n  entry 0: url /a != /b\n  \
+                    entry 1: method POST != GET");
+    }
+}
@@ -0,0 +1,175 @@
+//! Combining several logs into one.
+//!
+//! Page ids only need to be unique within a log, so logs captured separately (for example by
+//! test shards) often reuse them. Merging renames colliding page ids and rewrites the `pageref`
+//! of the affected entries to match.
+
+use std::collections::{HashMap, HashSet};
+
+use Log;
+
+/// Merges `logs` into a single log, in the order given.
+///
+/// Each log is named, and every merged entry records the name of its log in the custom
+/// `_source` field. A page whose id is already taken is renamed by appending `-2`, `-3`, ... to
+/// it. When `sort_by_time` is set, entries are sorted by `startedDateTime` (entries whose start
+/// cannot be parsed keep their relative order and go last).
+///
+/// The merged log is created by this crate; it takes the browser of the first log that names
+/// one.
+pub fn merge(logs: Vec<(String, Log)>, sort_by_time: bool) -> Log {
+    let mut merged = Log::new(None, None);
+    let mut page_ids = HashSet::new();
+
+    for (name, log) in logs {
+        if merged.browser.is_none() {
+            merged.browser = log.browser;
+        }
+
+        let mut renamed = HashMap::new();
+        for mut page in log.pages.unwrap_or_default() {
+            if !page_ids.insert(page.id.clone()) {
+                let id = (2..)
+                    .map(|suffix| format!("{}-{}", page.id, suffix))
+                    .find(|id| !page_ids.contains(id))
+                    .unwrap();
+                page_ids.insert(id.clone());
+                renamed.entry(page.id.clone()).or_insert_with(|| id.clone());
+                page.id = id;
+            }
+            merged.add_page(page);
+        }
+
+        for mut entry in log.entries {
+            if let Some(id) = entry.pageref.as_ref().and_then(|pageref| renamed.get(pageref)) {
+                entry.pageref = Some(id.clone());
+            }
+            entry.source = Some(name.clone());
+            merged.add_entry(entry);
+        }
+    }
+
+    if sort_by_time {
+        merged.entries.sort_by_key(|entry| {
+            let started = entry.started();
+            (started.is_none(), started)
+        });
+    }
+    merged
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use file;
+    use merge;
+    use Browser;
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Page;
+    use PageTimings;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn page(id: &str) -> Page {
+        Page::new("2009-04-16T12:07:25.123+01:00".to_string(), id.to_string(), id.to_string(),
+                  PageTimings::new(NotApplicable, NotApplicable, None), None)
+    }
+
+    fn entry(pageref: &str, started_date_time: &str, url: &str) -> Entry {
+        Entry::new(
+            Some(pageref.to_string()),
+            started_date_time.to_string(),
+            Request::new("GET".to_string(), url.to_string(), "HTTP/1.1".to_string(),
+                         Vec::new(), Vec::new(), Vec::new(), None, None, None, None),
+            Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(), Vec::new(),
+                          Content::new(0, None, "text/plain".to_string(), None, None, None),
+                          "".to_string(), None, None, None),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable, None),
+            None,
+            None,
+            None
+        )
+    }
+
+    fn shard(browser: Option<Browser>, entries: Vec<Entry>) -> Log {
+        let mut log = Log::new(browser, None);
+        log.add_page(page("page_0"));
+        log.add_page(page("page_1"));
+        for entry in entries {
+            log.add_entry(entry);
+        }
+        log
+    }
+
+    #[test]
+    fn test_merge_renames_colliding_pages() {
+        let first = shard(None, vec![
+            entry("page_0", "2009-04-16T12:07:23.000Z", "http://example.com/a"),
+        ]);
+        let second = shard(Some(Browser::new("Firefox".to_string(), "3.6".to_string(), None)),
+                           vec![
+            entry("page_1", "2009-04-16T12:07:22.000Z", "http://example.com/b"),
+            entry("page_0", "2009-04-16T12:07:21.000Z", "http://example.com/c"),
+        ]);
+        let merged = merge::merge(vec![("first".to_string(), first),
+                                       ("second".to_string(), second)], false);
+
+        let ids: Vec<&str> = merged.pages.as_ref().unwrap()
+            .iter()
+            .map(|page| page.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["page_0", "page_1", "page_0-2", "page_1-2"]);
+        let pagerefs: Vec<&str> = merged.entries()
+            .iter()
+            .map(|entry| entry.pageref.as_ref().unwrap().as_str())
+            .collect();
+        assert_eq!(pagerefs, vec!["page_0", "page_1-2", "page_0-2"]);
+        let sources: Vec<&str> = merged.entries()
+            .iter()
+            .map(|entry| entry.source().unwrap())
+            .collect();
+        assert_eq!(sources, vec!["first", "second", "second"]);
+        assert_eq!(merged.browser, Some(Browser::new("Firefox".to_string(), "3.6".to_string(),
+                                                     None)));
+    }
+
+    #[test]
+    fn test_merge_sorted_by_time() {
+        let first = shard(None, vec![
+            entry("page_0", "2009-04-16T12:07:23.000Z", "http://example.com/a"),
+            entry("page_0", "not a date", "http://example.com/d"),
+        ]);
+        let second = shard(None, vec![
+            entry("page_0", "2009-04-16T13:07:22.000+01:00", "http://example.com/b"),
+            entry("page_0", "2009-04-16T12:07:24.000Z", "http://example.com/c"),
+        ]);
+        let merged = merge::merge(vec![("first".to_string(), first),
+                                       ("second".to_string(), second)], true);
+        let urls: Vec<&str> = merged.entries()
+            .iter()
+            .map(|entry| entry.request.url.as_str())
+            .collect();
+        assert_eq!(urls, vec!["http://example.com/b", "http://example.com/a",
+                              "http://example.com/c", "http://example.com/d"]);
+    }
+
+    #[test]
+    fn test_merge_source_is_serialized() {
+        let merged = merge::merge(vec![("shard-1".to_string(), shard(None, vec![
+            entry("page_0", "2009-04-16T12:07:23.000Z", "http://example.com/a"),
+        ]))], false);
+        let mut written = Vec::new();
+        file::to_writer(&mut written, &merged).unwrap();
+        let text = String::from_utf8(written).unwrap();
+        assert!(text.contains("\"_source\": \"shard-1\""));
+        assert_eq!(file::from_reader(text.as_bytes()).unwrap(), merged);
+    }
+}
@@ -0,0 +1,313 @@
+//! Replacing secrets with stable stand-ins.
+//!
+//! Blank redaction hides which requests shared a session. A `Pseudonymizer` instead replaces
+//! each value the redaction `Rules` select with a keyed pseudonym: the same value always gets
+//! the same pseudonym under the same key, wherever it appears, so a session cookie still matches
+//! the token later sent in a header or URL.
+//!
+//! Pseudonyms keep the shape of the value they replace. They have the same number of
+//! characters, letters stay letters of the same case and digits stay digits; hexadecimal values
+//! such as UUIDs stay hexadecimal, and JWTs keep their header so they still look like JWTs.
+//! Letters and digits are always replaced with ASCII ones, whatever their script.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use redact::{Replace, Rules};
+use Log;
+
+/// Derives pseudonyms from a secret key.
+#[derive(Clone)]
+pub struct Pseudonymizer {
+    key: Vec<u8>,
+}
+
+impl Pseudonymizer {
+    /// Creates a pseudonymizer keyed with `key`. Anyone holding the key can test guesses of the
+    /// original values, so it should be kept as secret as they were.
+    pub fn new(key: &[u8]) -> Pseudonymizer {
+        Pseudonymizer { key: key.to_vec() }
+    }
+
+    /// Returns the pseudonym of `value`.
+    pub fn pseudonym(&self, value: &str) -> String {
+        let mut stream = KeyStream::new(&self.key, value);
+        if let Some((header, rest)) = split_jwt(value) {
+            return format!("{}.{}", header, map_chars(rest, &mut stream, false));
+        }
+        // Values of digits alone, such as card numbers, are not hexadecimal.
+        let hex = value.chars().any(|c| c.is_ascii_digit()) &&
+            value.chars().any(|c| c.is_ascii_hexdigit() && !c.is_ascii_digit()) &&
+            value.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+        map_chars(value, &mut stream, hex)
+    }
+
+    /// Replaces the value of a `name=value` cookie pair.
+    fn cookie_pair(&self, pair: &str) -> String {
+        match pair.find('=') {
+            Some(index) => format!("{}={}", &pair[..index], self.pseudonym(&pair[index + 1..])),
+            None => self.pseudonym(pair),
+        }
+    }
+}
+
+impl Replace for Pseudonymizer {
+    fn verb(&self) -> &'static str {
+        "pseudonymized"
+    }
+
+    fn value(&self, value: &str) -> String {
+        self.pseudonym(value)
+    }
+
+    /// Keeps the structure of credential headers, so that the cookies and tokens inside them get
+    /// the same pseudonyms as elsewhere in the log.
+    fn header(&self, name: &str, value: &str) -> String {
+        let name = name.to_ascii_lowercase();
+        if name == "authorization" || name == "proxy-authorization" {
+            if let Some(index) = value.find(' ') {
+                let (scheme, credentials) = value.split_at(index + 1);
+                return format!("{}{}", scheme, self.pseudonym(credentials));
+            }
+        } else if name == "cookie" {
+            return value.split("; ")
+                .map(|pair| self.cookie_pair(pair))
+                .collect::<Vec<_>>()
+                .join("; ");
+        } else if name == "set-cookie" {
+            let (pair, attributes) = match value.find(';') {
+                Some(index) => value.split_at(index),
+                None => (value, ""),
+            };
+            return format!("{}{}", self.cookie_pair(pair), attributes);
+        }
+        self.pseudonym(value)
+    }
+}
+
+impl Log {
+    /// Replaces every value `rules` select with its pseudonym, including in bodies shared by
+    /// `Log::dedup_bodies`.
+    pub fn pseudonymize(&mut self, rules: &Rules, pseudonymizer: &Pseudonymizer) {
+        self.rewrite_entries(|entry| rules.scrub(entry, pseudonymizer));
+    }
+}
+
+/// Splits a JWT into its header and the rest (payload and signature).
+fn split_jwt(value: &str) -> Option<(&str, &str)> {
+    let base64url = |part: &str| {
+        part.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+    let parts: Vec<&str> = value.split('.').collect();
+    if parts.len() != 3 || !parts[0].starts_with("eyJ") ||
+        !parts.iter().all(|part| base64url(part)) {
+        return None;
+    }
+    Some((parts[0], &value[parts[0].len() + 1..]))
+}
+
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"0123456789";
+const LOWER_HEX: &[u8] = b"0123456789abcdef";
+const UPPER_HEX: &[u8] = b"0123456789ABCDEF";
+
+/// Replaces every letter and digit of `value` with one of the same class drawn from `stream`,
+/// leaving everything else in place. With `hex`, hexadecimal digits are drawn from the
+/// hexadecimal digits instead.
+///
+/// Letters and digits outside ASCII are replaced with ASCII ones of the same case, so none of
+/// them survive; letters of scripts without case count as lowercase.
+fn map_chars(value: &str, stream: &mut KeyStream, hex: bool) -> String {
+    value.chars()
+        .map(|c| {
+            let alphabet = if hex && c.is_ascii_uppercase() {
+                UPPER_HEX
+            } else if hex && c.is_ascii_hexdigit() {
+                LOWER_HEX
+            } else if c.is_uppercase() {
+                UPPERCASE
+            } else if c.is_alphabetic() {
+                LOWERCASE
+            } else if c.is_numeric() {
+                DIGITS
+            } else {
+                return c;
+            };
+            alphabet[stream.next_byte() as usize % alphabet.len()] as char
+        })
+        .collect()
+}
+
+/// An endless sequence of bytes determined by a key and a value: the concatenated HMAC-SHA256
+/// digests of the value, each prefixed with the block number.
+struct KeyStream<'a> {
+    key: &'a [u8],
+    value: &'a str,
+    block: u32,
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl<'a> KeyStream<'a> {
+    fn new(key: &'a [u8], value: &'a str) -> KeyStream<'a> {
+        KeyStream { key, value, block: 0, bytes: Vec::new(), position: 0 }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        if self.position == self.bytes.len() {
+            let mut mac = Hmac::<Sha256>::new_from_slice(self.key)
+                .expect("HMAC accepts keys of any length");
+            mac.update(&self.block.to_be_bytes());
+            mac.update(self.value.as_bytes());
+            self.bytes = mac.finalize().into_bytes().to_vec();
+            self.block += 1;
+            self.position = 0;
+        }
+        self.position += 1;
+        self.bytes[self.position - 1]
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use pseudonymize::Pseudonymizer;
+    use redact::Rules;
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Cookie;
+    use Entry;
+    use Header;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(url: &str, headers: Vec<Header>, cookies: Vec<Cookie>) -> Entry {
+        Entry::new(
+            None,
+            "2009-04-16T12:07:20.000Z".to_string(),
+            Request::new("GET".to_string(), url.to_string(), "HTTP/1.1".to_string(), cookies,
+                         headers, Vec::new(), None, None, None, None),
+            Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(), Vec::new(),
+                          Content::new(0, None, "text/plain".to_string(), None, None, None),
+                          "".to_string(), None, None, None),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable, None),
+            None,
+            None,
+            None
+        )
+    }
+
+    fn header(name: &str, value: &str) -> Header {
+        Header::new(name.to_string(), value.to_string(), None)
+    }
+
+    #[test]
+    fn test_pseudonym_is_keyed_and_stable() {
+        let pseudonymizer = Pseudonymizer::new(b"key");
+        let pseudonym = pseudonymizer.pseudonym("s3ssion-ID");
+        assert_eq!(pseudonym, pseudonymizer.pseudonym("s3ssion-ID"));
+        assert_ne!(pseudonym, "s3ssion-ID");
+        assert_ne!(pseudonym, pseudonymizer.pseudonym("s3ssion-ID2")[..10]);
+        assert_ne!(pseudonym, Pseudonymizer::new(b"other key").pseudonym("s3ssion-ID"));
+        assert_eq!(pseudonymizer.pseudonym(""), "");
+    }
+
+    #[test]
+    fn test_pseudonym_keeps_format() {
+        let pseudonymizer = Pseudonymizer::new(b"key");
+
+        let pseudonym = pseudonymizer.pseudonym("aB3-xY9_z");
+        assert_eq!(pseudonym.len(), 9);
+        let classes = |text: &str| -> Vec<u8> {
+            text.chars()
+                .map(|c| if c.is_ascii_lowercase() {
+                    0
+                } else if c.is_ascii_uppercase() {
+                    1
+                } else if c.is_ascii_digit() {
+                    2
+                } else {
+                    c as u8
+                })
+                .collect()
+        };
+        assert_eq!(classes(&pseudonym), classes("aB3-xY9_z"));
+
+        let uuid = pseudonymizer.pseudonym("123e4567-e89b-12d3-a456-426614174000");
+        assert_eq!(uuid.len(), 36);
+        assert!(uuid.chars().enumerate().all(|(i, c)| if [8, 13, 18, 23].contains(&i) {
+            c == '-'
+        } else {
+            c.is_ascii_hexdigit() && !c.is_ascii_uppercase()
+        }));
+
+        for digits in &["12345", "4111111111111111", "2024-01-31"] {
+            let pseudonym = pseudonymizer.pseudonym(digits);
+            assert_eq!(classes(&pseudonym), classes(digits));
+        }
+
+        let jwt = "eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiIxMjM0In0.\
+                   dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let pseudonym = pseudonymizer.pseudonym(jwt);
+        assert_eq!(pseudonym.len(), jwt.len());
+        assert!(pseudonym.starts_with("eyJhbGciOiJIUzI1NiJ9."));
+        assert_ne!(pseudonym, jwt);
+        assert_eq!(pseudonym.matches('.').count(), 2);
+    }
+
+    #[test]
+    fn test_pseudonym_non_ascii() {
+        let pseudonymizer = Pseudonymizer::new(b"key");
+
+        let pseudonym = pseudonymizer.pseudonym("пароль");
+        assert_eq!(pseudonym.chars().count(), 6);
+        assert!(pseudonym.chars().all(|c| c.is_ascii_lowercase()));
+
+        let pseudonym = pseudonymizer.pseudonym("jöhn.dœ Ünï 密码 ٣");
+        let shape: String = pseudonym.chars()
+            .map(|c| if c.is_ascii_lowercase() {
+                'a'
+            } else if c.is_ascii_uppercase() {
+                'A'
+            } else if c.is_ascii_digit() {
+                '0'
+            } else {
+                c
+            })
+            .collect();
+        assert_eq!(shape, "aaaa.aa Aaa aa 0");
+    }
+
+    #[test]
+    fn test_pseudonymize_log_consistently() {
+        let cookie = Cookie::new("session".to_string(), "abc123".to_string(), None, None, None,
+                                 None, None, None);
+        let mut log = Log::new(None, None);
+        log.add_entry(entry("http://example.com/a",
+                            vec![header("Cookie", "session=abc123; theme=dark")],
+                            vec![cookie]));
+        log.add_entry(entry("http://example.com/b?token=abc123&page=2",
+                            vec![header("Authorization", "Bearer abc123")],
+                            Vec::new()));
+        let pseudonymizer = Pseudonymizer::new(b"key");
+        log.pseudonymize(&Rules::new(), &pseudonymizer);
+
+        let pseudonym = pseudonymizer.pseudonym("abc123");
+        let entries = log.entries();
+        assert_eq!(entries[0].request.cookies[0].value, pseudonym);
+        assert_eq!(entries[0].request.cookies[0].comment.as_deref(),
+                   Some("value pseudonymized"));
+        assert_eq!(entries[0].request.headers[0].value,
+                   format!("session={}; theme={}", pseudonym, pseudonymizer.pseudonym("dark")));
+        assert_eq!(entries[1].request.url,
+                   format!("http://example.com/b?token={}&page=2", pseudonym));
+        assert_eq!(entries[1].request.headers[0].value, format!("Bearer {}", pseudonym));
+    }
+}
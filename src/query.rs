@@ -0,0 +1,133 @@
+//! Keeping `queryString` in step with the request URL.
+//!
+//! HAR records the query twice: in the URL and as the decoded pairs of `queryString`. Tools that
+//! build logs by hand often fill only one of them. `Request::query_from_url` derives the pairs
+//! from the URL and `Request::url_from_query` rebuilds the URL from the pairs, both following
+//! the `application/x-www-form-urlencoded` rules: pairs are split on `&`, `+` stands for a space
+//! and everything else is percent-decoded. Repeated keys are kept in order.
+
+use url::{self, form_urlencoded, Url};
+
+use QueryStringPair;
+use Request;
+
+/// Parses a query string (without the leading `?`) into its pairs, in order. Invalid UTF-8
+/// after percent-decoding is replaced with U+FFFD.
+pub fn parse_query(query: &str) -> Vec<QueryStringPair> {
+    form_urlencoded::parse(query.as_bytes())
+        .map(|(name, value)| QueryStringPair::new(name.into_owned(), value.into_owned(), None))
+        .collect()
+}
+
+/// Encodes `pairs` as a query string (without the leading `?`).
+pub fn encode_query(pairs: &[QueryStringPair]) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs.iter().map(|pair| (&pair.name, &pair.value)))
+        .finish()
+}
+
+/// Whether the recorded `pair` stands for `parsed`. Browsers disagree on whether
+/// `queryString` holds decoded values or the text as it appears in the URL, so both are
+/// accepted.
+fn same_pair(pair: &QueryStringPair, parsed: &QueryStringPair) -> bool {
+    if pair.name == parsed.name && pair.value == parsed.value {
+        return true;
+    }
+    let decoded = parse_query(&format!("{}={}", pair.name, pair.value));
+    decoded.len() == 1 && decoded[0].name == parsed.name && decoded[0].value == parsed.value
+}
+
+impl Request {
+    /// The query pairs of `url`, or an error if the URL does not parse.
+    pub fn url_query(&self) -> Result<Vec<QueryStringPair>, url::ParseError> {
+        let url = Url::parse(&self.url)?;
+        Ok(url.query().map(parse_query).unwrap_or_default())
+    }
+
+    /// Replaces `query_string` with the pairs of the URL's query.
+    pub fn query_from_url(&mut self) -> Result<(), url::ParseError> {
+        self.query_string = self.url_query()?;
+        Ok(())
+    }
+
+    /// Replaces the query of `url` with `query_string`, dropping it if there are no pairs. The
+    /// rest of the URL is written in its normalized form.
+    pub fn url_from_query(&mut self) -> Result<(), url::ParseError> {
+        let mut url = Url::parse(&self.url)?;
+        if self.query_string.is_empty() {
+            url.set_query(None);
+        } else {
+            url.set_query(Some(&encode_query(&self.query_string)));
+        }
+        self.url = url.into();
+        Ok(())
+    }
+
+    /// Whether `query_string` holds the pairs of the URL's query, in order. A URL that does not
+    /// parse agrees with nothing.
+    pub fn query_matches_url(&self) -> bool {
+        match self.url_query() {
+            Ok(parsed) => {
+                parsed.len() == self.query_string.len() &&
+                    self.query_string.iter().zip(&parsed).all(|(pair, parsed)| {
+                        same_pair(pair, parsed)
+                    })
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use query;
+    use QueryStringPair;
+    use Request;
+
+    fn pair(name: &str, value: &str) -> QueryStringPair {
+        QueryStringPair::new(name.to_string(), value.to_string(), None)
+    }
+
+    fn request(url: &str, query_string: Vec<QueryStringPair>) -> Request {
+        Request::new("GET".to_string(), url.to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                     Vec::new(), query_string, None, None, None, None)
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(query::parse_query("q=a+b%2Bc&tag=1&tag=2&empty=&flag&%C3%A9=%E2%82%AC&&x=%ZZ"),
+                   vec![pair("q", "a b+c"), pair("tag", "1"), pair("tag", "2"), pair("empty", ""),
+                        pair("flag", ""), pair("é", "€"), pair("x", "%ZZ")]);
+        assert_eq!(query::encode_query(&[pair("q", "a b+c"), pair("é", "&")]),
+                   "q=a+b%2Bc&%C3%A9=%26");
+    }
+
+    #[test]
+    fn test_query_from_url() {
+        let mut request = request("http://example.com/search?q=a+b&page=2#results", Vec::new());
+        assert!(!request.query_matches_url());
+        request.query_from_url().unwrap();
+        assert_eq!(request.query_string, vec![pair("q", "a b"), pair("page", "2")]);
+        assert!(request.query_matches_url());
+
+        request.query_string.push(pair("lang", "en gb"));
+        request.url_from_query().unwrap();
+        assert_eq!(request.url, "http://example.com/search?q=a+b&page=2&lang=en+gb#results");
+
+        request.query_string.clear();
+        request.url_from_query().unwrap();
+        assert_eq!(request.url, "http://example.com/search#results");
+    }
+
+    #[test]
+    fn test_query_matches_url() {
+        let url = "http://example.com/?q=a%20b&r=%2F";
+        assert!(request(url, vec![pair("q", "a b"), pair("r", "/")]).query_matches_url());
+        assert!(request(url, vec![pair("q", "a%20b"), pair("r", "%2F")]).query_matches_url());
+        assert!(!request(url, vec![pair("r", "/"), pair("q", "a b")]).query_matches_url());
+        assert!(!request(url, vec![pair("q", "a b")]).query_matches_url());
+        assert!(!request("/relative", Vec::new()).query_matches_url());
+    }
+}
@@ -0,0 +1,836 @@
+//! Scrubbing secrets from a log before it is shared.
+//!
+//! `Rules` list what to scrub: headers, cookie values, query and form parameters, fields of JSON
+//! bodies and the server address. The defaults cover the usual credentials; they can be
+//! extended or relaxed:
+//!
+//! ```
+//! use har::redact::Rules;
+//!
+//! let rules = Rules::new()
+//!     .redact_header("X-Tenant")
+//!     .keep_param("code")
+//!     .redact_json_path("$.user.email")
+//!     .unwrap();
+//! ```
+//!
+//! Every scrubbed value is replaced with `[REDACTED]`, and the object holding it gets a comment
+//! saying what was removed, so readers of the log can tell a scrubbed value from a recorded one.
+
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+
+use base64;
+use base64::Engine;
+use serde_json::{self, Value};
+use url::form_urlencoded;
+
+use form::{self, FormError};
+use Content;
+use Entry;
+use Header;
+use Log;
+use PostData;
+
+/// What scrubbed values are replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Headers carrying credentials.
+const SECRET_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+    "x-api-key",
+    "x-auth-token",
+    "x-csrf-token",
+];
+
+/// Headers holding a URL, whose query is scrubbed like the request URL's.
+const URL_HEADERS: &[&str] = &["content-location", "location", "referer"];
+
+/// Parameter and JSON field names that usually hold credentials.
+const SECRET_NAMES: &[&str] = &[
+    "access_token",
+    "api_key",
+    "apikey",
+    "client_secret",
+    "code",
+    "id_token",
+    "password",
+    "refresh_token",
+    "secret",
+    "session",
+    "sessionid",
+    "sig",
+    "signature",
+    "token",
+];
+
+/// What to scrub from a log.
+#[derive(Clone, Debug)]
+pub struct Rules {
+    headers: HashSet<String>,
+    cookies: bool,
+    params: HashSet<String>,
+    json_paths: Vec<JsonPath>,
+    server_ip_address: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules::new()
+    }
+}
+
+impl Rules {
+    /// Creates the default rules. They scrub `Authorization`, `Cookie`, `Set-Cookie` and
+    /// similar headers, all cookie values, the server address, and query parameters, form
+    /// parameters and JSON fields with names such as `token`, `password` or `api_key`. Like
+    /// parameter names, the JSON field names are compared case-insensitively.
+    pub fn new() -> Rules {
+        let mut rules = Rules::none()
+            .redact_cookies(true)
+            .redact_server_ip_address(true);
+        for header in SECRET_HEADERS {
+            rules = rules.redact_header(header);
+        }
+        for name in SECRET_NAMES {
+            rules = rules.redact_param(name);
+            rules.json_paths.push(JsonPath {
+                text: format!("$..{}", name),
+                steps: vec![Step::Descendant(Selector::KeyAnyCase(name.to_string()))],
+            });
+        }
+        rules
+    }
+
+    /// Creates rules that scrub nothing, as a starting point for a custom set.
+    pub fn none() -> Rules {
+        Rules {
+            headers: HashSet::new(),
+            cookies: false,
+            params: HashSet::new(),
+            json_paths: Vec::new(),
+            server_ip_address: false,
+        }
+    }
+
+    /// Scrub the values of request and response headers called `name` (case-insensitive).
+    pub fn redact_header(mut self, name: &str) -> Rules {
+        self.headers.insert(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Leave headers called `name` (case-insensitive) alone.
+    pub fn keep_header(mut self, name: &str) -> Rules {
+        self.headers.remove(&name.to_ascii_lowercase());
+        self
+    }
+
+    /// Scrub the values of all request and response cookies.
+    pub fn redact_cookies(mut self, enabled: bool) -> Rules {
+        self.cookies = enabled;
+        self
+    }
+
+    /// Scrub query and form parameters called `name` (case-insensitive), both in the parsed
+    /// parameter lists and in the URL or body text they came from.
+    pub fn redact_param(mut self, name: &str) -> Rules {
+        self.params.insert(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Leave parameters called `name` (case-insensitive) alone. JSON fields of that name are
+    /// still scrubbed by the default paths; start from `Rules::none()` to avoid that.
+    pub fn keep_param(mut self, name: &str) -> Rules {
+        self.params.remove(&name.to_ascii_lowercase());
+        self
+    }
+
+    /// Scrub the values at `path` in JSON request and response bodies.
+    ///
+    /// Paths start at `$` and select object fields with `.name` or `['name']`, array elements
+    /// with `[0]` and any child with `.*` or `[*]`. `..name` selects the field at any depth.
+    pub fn redact_json_path(mut self, path: &str) -> Result<Rules, InvalidPath> {
+        self.json_paths.push(JsonPath::parse(path)?);
+        Ok(self)
+    }
+
+    /// Scrub `serverIPAddress`.
+    pub fn redact_server_ip_address(mut self, enabled: bool) -> Rules {
+        self.server_ip_address = enabled;
+        self
+    }
+
+    /// Scrubs `entry` according to these rules.
+    pub fn apply(&self, entry: &mut Entry) {
+        self.scrub(entry, &Redact);
+    }
+
+    /// Replaces the values these rules select in `entry` using `replace`.
+    pub(crate) fn scrub(&self, entry: &mut Entry, replace: &dyn Replace) {
+        let marker = format!("value {}", replace.verb());
+        if self.server_ip_address {
+            if let Some(address) = entry.server_ip_address.take() {
+                entry.server_ip_address = Some(replace.value(&address));
+                mark(&mut entry.comment, &format!("serverIPAddress {}", replace.verb()));
+            }
+        }
+
+        let request = &mut entry.request;
+        self.scrub_headers(&mut request.headers, replace);
+        for pair in &mut request.query_string {
+            if self.params.contains(&pair.name.to_ascii_lowercase()) {
+                pair.value = replace.value(&pair.value);
+                mark(&mut pair.comment, &marker);
+            }
+        }
+        if let Some(url) = self.scrub_url(&request.url, replace) {
+            request.url = url;
+            mark(&mut request.comment, &format!("url query {}", replace.verb()));
+        }
+        if let Some(ref mut post_data) = request.post_data {
+            self.scrub_post_data(post_data, replace);
+        }
+
+        let response = &mut entry.response;
+        self.scrub_headers(&mut response.headers, replace);
+        if let Some(url) = self.scrub_url(&response.redirect_url, replace) {
+            response.redirect_url = url;
+            mark(&mut response.comment, &format!("redirectURL query {}", replace.verb()));
+        }
+        self.scrub_content(&mut response.content, replace);
+
+        if self.cookies {
+            for cookie in request.cookies.iter_mut().chain(response.cookies.iter_mut()) {
+                cookie.value = replace.value(&cookie.value);
+                mark(&mut cookie.comment, &marker);
+            }
+        }
+    }
+
+    /// Returns `url` with the denied query parameters replaced, or `None` if it has none.
+    /// Scrubs the denied headers, and the query of headers holding a URL.
+    fn scrub_headers(&self, headers: &mut [Header], replace: &dyn Replace) {
+        for header in headers {
+            let name = header.name.to_ascii_lowercase();
+            if self.headers.contains(&name) {
+                header.value = replace.header(&header.name, &header.value);
+                mark(&mut header.comment, &format!("value {}", replace.verb()));
+            } else if URL_HEADERS.contains(&name.as_str()) {
+                if let Some(url) = self.scrub_url(&header.value, replace) {
+                    header.value = url;
+                    mark(&mut header.comment, &format!("url query {}", replace.verb()));
+                }
+            }
+        }
+    }
+
+    fn scrub_url(&self, url: &str, replace: &dyn Replace) -> Option<String> {
+        let (url, fragment) = match url.find('#') {
+            Some(index) => url.split_at(index),
+            None => (url, ""),
+        };
+        let index = url.find('?')?;
+        let query = self.scrub_form(&url[index + 1..], replace)?;
+        Some(format!("{}?{}{}", &url[..index], query, fragment))
+    }
+
+    /// Returns the `application/x-www-form-urlencoded` text `form` with the denied parameters
+    /// replaced, or `None` if it has none. Other parameters are left exactly as they were.
+    fn scrub_form(&self, form: &str, replace: &dyn Replace) -> Option<String> {
+        let mut changed = false;
+        let pairs: Vec<String> = form.split('&')
+            .map(|pair| {
+                let parsed = form_urlencoded::parse(pair.as_bytes()).next();
+                match parsed {
+                    Some((ref name, ref value))
+                        if self.params.contains(&name.to_ascii_lowercase()) => {
+                        changed = true;
+                        let raw_name = pair.split('=').next().unwrap_or("");
+                        let value: String =
+                            form_urlencoded::byte_serialize(replace.value(value).as_bytes())
+                                .collect();
+                        format!("{}={}", raw_name, value)
+                    }
+                    _ => pair.to_string(),
+                }
+            })
+            .collect();
+        if changed {
+            Some(pairs.join("&"))
+        } else {
+            None
+        }
+    }
+
+    fn scrub_post_data(&self, post_data: &mut PostData, replace: &dyn Replace) {
+        for param in &mut post_data.params {
+            if !self.params.contains(&param.name.to_ascii_lowercase()) {
+                continue;
+            }
+            if let Some(value) = param.value.take() {
+                param.value = Some(replace.value(&value));
+                param.encoding = None;
+                mark(&mut param.comment, &format!("value {}", replace.verb()));
+            }
+        }
+        if form::is_type(&post_data.mime_type, "multipart/form-data") {
+            self.scrub_multipart(post_data, replace);
+        } else if post_data.mime_type.contains("x-www-form-urlencoded") {
+            if let Some(text) = self.scrub_form(&post_data.text, replace) {
+                post_data.text = text;
+                mark(&mut post_data.comment, &format!("form parameters {}", replace.verb()));
+            }
+        } else if post_data.mime_type.contains("json") {
+            if let Some((text, paths)) = self.scrub_json(post_data.text.as_bytes(), replace) {
+                post_data.text = text;
+                for path in paths {
+                    mark(&mut post_data.comment, &format!("{} {}", path, replace.verb()));
+                }
+            }
+        }
+    }
+
+    /// Rewrites the multipart `text` of `post_data` from its scrubbed parts. A body that does
+    /// not parse cannot be scrubbed part by part, so its text is dropped rather than kept as it
+    /// was.
+    fn scrub_multipart(&self, post_data: &mut PostData, replace: &dyn Replace) {
+        if post_data.text.is_empty() {
+            return;
+        }
+        let parsed = form::boundary(&post_data.mime_type)
+            .ok_or(FormError::MissingBoundary)
+            .and_then(|boundary| {
+                form::parse_multipart(post_data.text.as_bytes(), &boundary)
+                    .map(|params| (boundary, params))
+            });
+        let (boundary, mut params) = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                post_data.text.clear();
+                mark(&mut post_data.comment, &format!("text dropped: {}", err));
+                return;
+            }
+        };
+        let mut changed = false;
+        for param in &mut params {
+            if self.params.contains(&param.name.to_ascii_lowercase()) {
+                if let Some(value) = param.value.take() {
+                    param.value = Some(replace.value(&value));
+                    param.encoding = None;
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            let text = form::encode_multipart(&params, &boundary);
+            post_data.text = String::from_utf8_lossy(&text).into_owned();
+            mark(&mut post_data.comment, &format!("form parameters {}", replace.verb()));
+        }
+    }
+
+    fn scrub_content(&self, content: &mut Content, replace: &dyn Replace) {
+        if !content.mime_type.contains("json") {
+            return;
+        }
+        let body = match content.body_bytes() {
+            Ok(body) => body,
+            Err(_) => return,
+        };
+        if let Some((text, paths)) = self.scrub_json(&body, replace) {
+            content.text = if content.encoding.is_some() {
+                Some(base64::engine::general_purpose::STANDARD.encode(text.as_bytes()))
+            } else {
+                Some(text)
+            };
+            for path in paths {
+                mark(&mut content.comment, &format!("{} {}", path, replace.verb()));
+            }
+        }
+    }
+
+    /// Replaces the selected values of the JSON document `body`, returning its new text and the
+    /// paths that selected something, or `None` if it is not JSON or nothing was selected.
+    fn scrub_json(&self, body: &[u8], replace: &dyn Replace) -> Option<(String, Vec<&str>)> {
+        let mut json: Value = serde_json::from_slice(body).ok()?;
+        let paths: Vec<&str> = self.json_paths.iter()
+            .filter(|path| scrub_value(&mut json, &path.steps, replace))
+            .map(|path| path.text.as_str())
+            .collect();
+        if paths.is_empty() {
+            return None;
+        }
+        serde_json::to_string(&json).ok().map(|text| (text, paths))
+    }
+}
+
+/// How selected values are replaced.
+pub(crate) trait Replace {
+    /// The past participle describing the replacement in marker comments, e.g. "redacted".
+    fn verb(&self) -> &'static str;
+
+    /// The replacement for `value`.
+    fn value(&self, value: &str) -> String;
+
+    /// The replacement for the value of the header `name`.
+    fn header(&self, _name: &str, value: &str) -> String {
+        self.value(value)
+    }
+
+    /// The replacement for a JSON value. Numbers stay numbers if their replacement is numeric.
+    fn json(&self, value: &Value) -> Value {
+        let replacement = match *value {
+            Value::String(ref text) => self.value(text),
+            ref other => self.value(&other.to_string()),
+        };
+        if value.is_number() {
+            if let Ok(number @ Value::Number(_)) = serde_json::from_str(&replacement) {
+                return number;
+            }
+        }
+        Value::String(replacement)
+    }
+}
+
+/// Replaces every value with `REDACTED`.
+struct Redact;
+
+impl Replace for Redact {
+    fn verb(&self) -> &'static str {
+        "redacted"
+    }
+
+    fn value(&self, _value: &str) -> String {
+        REDACTED.to_string()
+    }
+}
+
+/// Appends `note` to `comment`, unless it is already there.
+fn mark(comment: &mut Option<String>, note: &str) {
+    *comment = match comment.take() {
+        None => Some(note.to_string()),
+        Some(ref existing) if existing.split("; ").any(|part| part == note) => {
+            Some(existing.clone())
+        }
+        Some(existing) => Some(format!("{}; {}", existing, note)),
+    };
+}
+
+impl Log {
+    /// Scrubs every entry according to `rules`, including bodies shared by
+    /// `Log::dedup_bodies`.
+    pub fn redact(&mut self, rules: &Rules) {
+        self.rewrite_entries(|entry| rules.apply(entry));
+    }
+}
+
+/// A JSON path that could not be parsed.
+#[derive(Debug)]
+pub struct InvalidPath {
+    path: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for InvalidPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid JSON path \"{}\": {}", self.path, self.reason)
+    }
+}
+
+impl error::Error for InvalidPath {}
+
+#[derive(Clone, Debug)]
+struct JsonPath {
+    text: String,
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Child(Selector),
+    Descendant(Selector),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Selector {
+    Key(String),
+    /// A key compared case-insensitively; only the default paths use it.
+    KeyAnyCase(String),
+    Index(usize),
+    Any,
+}
+
+impl JsonPath {
+    fn parse(text: &str) -> Result<JsonPath, InvalidPath> {
+        let invalid = |reason| InvalidPath { path: text.to_string(), reason };
+        let mut rest = match text.strip_prefix('$') {
+            Some(rest) => rest,
+            None => return Err(invalid("paths start with $")),
+        };
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            let descendant = rest.starts_with("..");
+            if descendant {
+                rest = &rest[1..];
+            }
+            let selector = if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                rest = &after[end..];
+                match &after[..end] {
+                    "" => return Err(invalid("empty field name")),
+                    "*" => Selector::Any,
+                    name => Selector::Key(name.to_string()),
+                }
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = match after.find(']') {
+                    Some(end) => end,
+                    None => return Err(invalid("unclosed [")),
+                };
+                rest = &after[end + 1..];
+                let inside = &after[..end];
+                let quoted = inside.len() >= 2 &&
+                    ((inside.starts_with('\'') && inside.ends_with('\'')) ||
+                     (inside.starts_with('"') && inside.ends_with('"')));
+                if inside == "*" {
+                    Selector::Any
+                } else if quoted {
+                    Selector::Key(inside[1..inside.len() - 1].to_string())
+                } else {
+                    match inside.parse() {
+                        Ok(index) => Selector::Index(index),
+                        Err(_) => return Err(invalid("expected an index, * or a quoted name")),
+                    }
+                }
+            } else {
+                return Err(invalid("expected . or ["));
+            };
+            steps.push(if descendant { Step::Descendant(selector) } else { Step::Child(selector) });
+        }
+        if steps.is_empty() {
+            return Err(invalid("the whole document cannot be redacted"));
+        }
+        Ok(JsonPath { text: text.to_string(), steps })
+    }
+}
+
+/// Replaces the values `steps` select below `value`, returning whether there were any.
+fn scrub_value(value: &mut Value, steps: &[Step], replace: &dyn Replace) -> bool {
+    let (step, rest) = match steps.split_first() {
+        Some(split) => split,
+        None => {
+            *value = replace.json(value);
+            return true;
+        }
+    };
+    let selector = match *step {
+        Step::Child(ref selector) | Step::Descendant(ref selector) => selector,
+    };
+    let mut found = false;
+    match *value {
+        Value::Object(ref mut map) => {
+            for (key, child) in map.iter_mut() {
+                let selected = match *selector {
+                    Selector::Key(ref name) => name == key,
+                    Selector::KeyAnyCase(ref name) => name.eq_ignore_ascii_case(key),
+                    Selector::Index(_) => false,
+                    Selector::Any => true,
+                };
+                found |= visit(child, selected, step, steps, rest, replace);
+            }
+        }
+        Value::Array(ref mut items) => {
+            for (index, child) in items.iter_mut().enumerate() {
+                let selected = match *selector {
+                    Selector::Key(_) | Selector::KeyAnyCase(_) => false,
+                    Selector::Index(wanted) => wanted == index,
+                    Selector::Any => true,
+                };
+                found |= visit(child, selected, step, steps, rest, replace);
+            }
+        }
+        _ => {}
+    }
+    found
+}
+
+/// Continues `scrub_value` into `child`: with the remaining steps if the current step selected
+/// it, and with the current step again if that step searches at any depth.
+fn visit(child: &mut Value,
+         selected: bool,
+         step: &Step,
+         steps: &[Step],
+         rest: &[Step],
+         replace: &dyn Replace) -> bool {
+    let mut found = false;
+    if let Step::Descendant(_) = *step {
+        found |= scrub_value(child, steps, replace);
+    }
+    if selected {
+        found |= scrub_value(child, rest, replace);
+    }
+    found
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use serde_json::{self, Value};
+
+    use redact::{JsonPath, Rules, Selector, Step, REDACTED};
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Cookie;
+    use Entry;
+    use Header;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Param;
+    use PostData;
+    use QueryStringPair;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn header(name: &str, value: &str) -> Header {
+        Header::new(name.to_string(), value.to_string(), None)
+    }
+
+    fn cookie(name: &str, value: &str) -> Cookie {
+        Cookie::new(name.to_string(), value.to_string(), None, None, None, None, None, None)
+    }
+
+    fn entry(post_data: PostData, response_text: &str) -> Entry {
+        Entry::new(
+            None,
+            "2009-04-16T12:07:20.000Z".to_string(),
+            Request::new(
+                "POST".to_string(),
+                "https://example.com/login?user=bob&access_token=abc%20def#top".to_string(),
+                "HTTP/1.1".to_string(),
+                vec![cookie("session", "s3cr3t")],
+                vec![header("Authorization", "Bearer abc"), header("Accept", "*/*")],
+                vec![QueryStringPair::new("user".to_string(), "bob".to_string(), None),
+                     QueryStringPair::new("access_token".to_string(), "abc def".to_string(),
+                                          Some("from the login form".to_string()))],
+                Some(post_data),
+                None,
+                None,
+                None
+            ),
+            Response::new(
+                200,
+                "OK".to_string(),
+                "HTTP/1.1".to_string(),
+                vec![cookie("session", "n3w")],
+                vec![header("set-cookie", "session=n3w"), header("Content-Type", "text/json")],
+                Content::new(response_text.len() as i32, None, "application/json".to_string(),
+                             Some(response_text.to_string()), None, None),
+                "".to_string(),
+                None,
+                None,
+                None
+            ),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable, None),
+            Some("93.184.216.34".to_string()),
+            None,
+            None
+        )
+    }
+
+    fn form() -> PostData {
+        PostData::new(
+            "application/x-www-form-urlencoded".to_string(),
+            vec![Param::new("user".to_string(), Some("bob".to_string()), None, None, None),
+                 Param::new("Password".to_string(), Some("hunter2".to_string()), None, None,
+                            None)],
+            "user=bob&Password=hunter2".to_string(),
+            None
+        )
+    }
+
+    fn json(text: &str) -> Value {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let mut entry = entry(form(), "{\"user\": {\"name\": \"bob\", \"token\": \"t0k\"}}");
+        Rules::new().apply(&mut entry);
+        let request = &entry.request;
+        let response = &entry.response;
+
+        assert_eq!(entry.server_ip_address.as_deref(), Some(REDACTED));
+        assert_eq!(entry.comment.as_deref(), Some("serverIPAddress redacted"));
+
+        assert_eq!(request.headers[0].value, REDACTED);
+        assert_eq!(request.headers[0].comment.as_deref(), Some("value redacted"));
+        assert_eq!(request.headers[1].value, "*/*");
+        assert_eq!(request.headers[1].comment, None);
+        assert_eq!(response.headers[0].value, REDACTED);
+        assert_eq!(response.headers[1].value, "text/json");
+
+        assert_eq!(request.cookies[0].value, REDACTED);
+        assert_eq!(response.cookies[0].value, REDACTED);
+        assert_eq!(response.cookies[0].comment.as_deref(), Some("value redacted"));
+
+        assert_eq!(request.url,
+                   "https://example.com/login?user=bob&access_token=%5BREDACTED%5D#top");
+        assert_eq!(request.comment.as_deref(), Some("url query redacted"));
+        assert_eq!(request.query_string[0].value, "bob");
+        assert_eq!(request.query_string[1].value, REDACTED);
+        assert_eq!(request.query_string[1].comment.as_deref(),
+                   Some("from the login form; value redacted"));
+
+        let post_data = request.post_data.as_ref().unwrap();
+        assert_eq!(post_data.params[0].value.as_deref(), Some("bob"));
+        assert_eq!(post_data.params[1].value.as_deref(), Some(REDACTED));
+        assert_eq!(post_data.text, "user=bob&Password=%5BREDACTED%5D");
+        assert_eq!(post_data.comment.as_deref(), Some("form parameters redacted"));
+
+        assert_eq!(json(response.content.text.as_ref().unwrap()),
+                   json("{\"user\": {\"name\": \"bob\", \"token\": \"[REDACTED]\"}}"));
+        assert_eq!(response.content.comment.as_deref(), Some("$..token redacted"));
+    }
+
+    #[test]
+    fn test_default_rules_json_case() {
+        let text = "{\"Password\":\"hunter2\",\"password\":\"x\",\"Token\":\"t\"}";
+        let mut entry = entry(form(), text);
+        Rules::new().apply(&mut entry);
+        assert_eq!(json(entry.response.content.text.as_ref().unwrap()),
+                   json("{\"Password\": \"[REDACTED]\", \"password\": \"[REDACTED]\", \
+                          \"Token\": \"[REDACTED]\"}"));
+    }
+
+    #[test]
+    fn test_redact_url_headers() {
+        let mut entry = entry(form(), "{}");
+        entry.request.headers.push(header("Referer", "https://example.com/?token=abc&x=1"));
+        entry.response.headers.push(header("location", "/next?code=123#top"));
+        entry.response.headers.push(header("Content-Location", "/next?x=1"));
+        Rules::new().apply(&mut entry);
+        assert_eq!(entry.request.headers[2].value, "https://example.com/?token=%5BREDACTED%5D&x=1");
+        assert_eq!(entry.request.headers[2].comment.as_deref(), Some("url query redacted"));
+        assert_eq!(entry.response.headers[2].value, "/next?code=%5BREDACTED%5D#top");
+        assert_eq!(entry.response.headers[3].value, "/next?x=1");
+        assert_eq!(entry.response.headers[3].comment, None);
+    }
+
+    #[test]
+    fn test_redact_is_idempotent() {
+        let mut log = Log::new(None, None);
+        log.add_entry(entry(form(), "{}"));
+        log.redact(&Rules::new());
+        let once = log.clone();
+        log.redact(&Rules::new());
+        assert_eq!(log, once);
+    }
+
+    #[test]
+    fn test_redact_shared_bodies() {
+        let mut log = Log::new(None, None);
+        log.add_entry(entry(form(), "{\"token\":\"SECRETTOKEN\"}"));
+        log.add_entry(entry(form(), "{\"token\":\"SECRETTOKEN\"}"));
+        assert_eq!(log.dedup_bodies(), 1);
+        log.redact(&Rules::new());
+        assert_eq!(log.bodies.len(), 1);
+        assert!(!serde_json::to_string(&log).unwrap().contains("SECRETTOKEN"));
+
+        log.expand_bodies();
+        for entry in log.entries() {
+            assert_eq!(json(entry.response.content.text.as_ref().unwrap()),
+                       json("{\"token\": \"[REDACTED]\"}"));
+        }
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let rules = Rules::none()
+            .redact_header("accept")
+            .redact_param("user")
+            .redact_json_path("$.items[*].id").unwrap()
+            .redact_json_path("$['odd key']").unwrap();
+        let post_data = PostData::new("application/json".to_string(), Vec::new(),
+                                      "{\"password\": \"hunter2\", \"odd key\": 1}".to_string(),
+                                      None);
+        let mut entry = entry(post_data, "{\"items\": [{\"id\": 1}, {\"id\": 2, \"n\": 3}]}");
+        rules.apply(&mut entry);
+
+        assert_eq!(entry.server_ip_address.as_deref(), Some("93.184.216.34"));
+        assert_eq!(entry.request.headers[0].value, "Bearer abc");
+        assert_eq!(entry.request.headers[1].value, REDACTED);
+        assert_eq!(entry.request.cookies[0].value, "s3cr3t");
+        assert_eq!(entry.request.url,
+                   "https://example.com/login?user=%5BREDACTED%5D&access_token=abc%20def#top");
+
+        let post_data = entry.request.post_data.as_ref().unwrap();
+        assert_eq!(json(&post_data.text),
+                   json("{\"password\": \"hunter2\", \"odd key\": \"[REDACTED]\"}"));
+        assert_eq!(post_data.comment.as_deref(), Some("$['odd key'] redacted"));
+        assert_eq!(json(entry.response.content.text.as_ref().unwrap()),
+                   json("{\"items\": [{\"id\": \"[REDACTED]\"}, \
+                          {\"id\": \"[REDACTED]\", \"n\": 3}]}"));
+    }
+
+    #[test]
+    fn test_redact_multipart() {
+        let mime_type = "multipart/form-data; boundary=XyZ".to_string();
+        let text = "--XyZ\r\nContent-Disposition: form-data; name=\"user\"\r\n\r\nbob\r\n\
+                    --XyZ\r\nContent-Disposition: form-data; name=\"password\"\r\n\r\n\
+                    hunter2\r\n--XyZ--\r\n";
+        let mut parsed = entry(PostData::new(mime_type.clone(), Vec::new(), text.to_string(),
+                                             None),
+                               "{}");
+        Rules::new().apply(&mut parsed);
+        let post_data = parsed.request.post_data.as_ref().unwrap();
+        assert!(!post_data.text.contains("hunter2"));
+        assert!(post_data.text.contains(REDACTED));
+        assert!(post_data.text.contains("bob"));
+        assert_eq!(post_data.comment.as_deref(), Some("form parameters redacted"));
+
+        let mut malformed = entry(PostData::new(mime_type, Vec::new(),
+                                                "password=hunter2".to_string(), None),
+                                  "{}");
+        Rules::new().apply(&mut malformed);
+        let post_data = malformed.request.post_data.as_ref().unwrap();
+        assert_eq!(post_data.text, "");
+        assert!(post_data.comment.as_ref().unwrap().starts_with("text dropped: "));
+    }
+
+    #[test]
+    fn test_redact_base64_content() {
+        let mut entry = entry(form(), "");
+        entry.response.content = Content::new(15, None, "application/json".to_string(),
+                                              Some("eyJ0b2tlbiI6ICJ4In0=".to_string()),
+                                              Some("base64".to_string()), None);
+        Rules::new().apply(&mut entry);
+        let body = entry.response.content.body_bytes().unwrap();
+        assert_eq!(String::from_utf8(body).unwrap(), "{\"token\":\"[REDACTED]\"}");
+    }
+
+    #[test]
+    fn test_parse_json_path() {
+        let path = JsonPath::parse("$.a[0]..b[*].*['c.d']").unwrap();
+        assert_eq!(path.steps, vec![
+            Step::Child(Selector::Key("a".to_string())),
+            Step::Child(Selector::Index(0)),
+            Step::Descendant(Selector::Key("b".to_string())),
+            Step::Child(Selector::Any),
+            Step::Child(Selector::Any),
+            Step::Child(Selector::Key("c.d".to_string())),
+        ]);
+        assert!(JsonPath::parse("a.b").is_err());
+        assert!(JsonPath::parse("$").is_err());
+        assert!(JsonPath::parse("$.").is_err());
+        assert!(JsonPath::parse("$[x]").is_err());
+        assert!(JsonPath::parse("$[0").is_err());
+        assert_eq!(JsonPath::parse("$[x]").unwrap_err().to_string(),
+                   "invalid JSON path \"$[x]\": expected an index, * or a quoted name");
+    }
+}
@@ -0,0 +1,292 @@
+//! Standalone HTML reports.
+//!
+//! `html` renders a log as a single HTML file with no external assets, so it can be attached to
+//! CI runs and opened offline. Entries are grouped by page, each group with its waterfall and a
+//! sortable table; entries outside any page get a group of their own. Clicking an entry shows its
+//! headers and bodies.
+
+use std::fmt::Write;
+
+use waterfall::{escape, Waterfall};
+use Content;
+use Entry;
+use Header;
+use Log;
+
+/// Bodies longer than this are cut in the report.
+const MAX_BODY_SHOWN: usize = 256 * 1024;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; font-size: 13px; margin: 1em 2em; }
+h2 { margin-top: 2em; }
+table.entries { border-collapse: collapse; width: 100%; }
+table.entries th { cursor: pointer; text-align: left; background: #eee; user-select: none; }
+table.entries th, table.entries td { padding: 2px 6px; border-bottom: 1px solid #ddd; }
+table.entries td.url { word-break: break-all; }
+tr.entry { cursor: pointer; }
+tr.entry:hover { background: #f4f8ff; }
+tr.error td { color: #b91c1c; }
+tr.details { display: none; }
+tr.details.open { display: table-row; }
+tr.details td { background: #fafafa; }
+pre { white-space: pre-wrap; word-break: break-all; max-height: 30em; overflow: auto;
+      background: #fff; border: 1px solid #ddd; padding: 4px; }
+.waterfall { overflow-x: auto; }
+";
+
+const SCRIPT: &str = "\
+document.querySelectorAll('tr.entry').forEach(function (row) {
+  row.addEventListener('click', function () {
+    row.nextElementSibling.classList.toggle('open');
+  });
+});
+document.querySelectorAll('table.entries th').forEach(function (th, column) {
+  th.addEventListener('click', function () {
+    var body = th.closest('table').tBodies[0];
+    var ascending = th.dataset.order !== 'asc';
+    th.dataset.order = ascending ? 'asc' : 'desc';
+    var pairs = [];
+    for (var i = 0; i < body.rows.length; i += 2) {
+      pairs.push([body.rows[i], body.rows[i + 1]]);
+    }
+    pairs.sort(function (a, b) {
+      var x = a[0].cells[column].dataset.value, y = b[0].cells[column].dataset.value;
+      var order = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+      return ascending ? order : -order;
+    });
+    pairs.forEach(function (pair) { body.appendChild(pair[0]); body.appendChild(pair[1]); });
+  });
+});
+";
+
+/// Renders `log` as a standalone HTML document titled `title`.
+pub fn html(log: &Log, title: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(out, "<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>",
+             escape(title), STYLE).unwrap();
+    writeln!(out, "<h1>{}</h1>", escape(title)).unwrap();
+    writeln!(out, "<p>{} requests, created by {} {}</p>",
+             log.entries.len(), escape(&log.creator.name), escape(&log.creator.version)).unwrap();
+
+    let mut index = 0;
+    for page in log.pages.iter().flatten() {
+        let entries: Vec<(usize, &Entry)> = log.entries.iter()
+            .enumerate()
+            .filter(|&(_, entry)| entry.pageref.as_ref() == Some(&page.id))
+            .collect();
+        writeln!(out, "<h2>{} <small>({}, started {})</small></h2>",
+                 escape(&page.title), escape(&page.id), escape(&page.started_date_time))
+            .unwrap();
+        if let Some(waterfall) = Waterfall::from_page(log, &page.id) {
+            writeln!(out, "<div class=\"waterfall\">{}</div>", waterfall.to_svg()).unwrap();
+        }
+        write_table(&mut out, index, &entries);
+        index += 1;
+    }
+
+    let pages: Vec<&str> = log.pages.iter().flatten().map(|page| page.id.as_str()).collect();
+    let orphans: Vec<(usize, &Entry)> = log.entries.iter()
+        .enumerate()
+        .filter(|&(_, entry)| {
+            entry.pageref.as_ref().is_none_or(|pageref| !pages.contains(&pageref.as_str()))
+        })
+        .collect();
+    if !orphans.is_empty() {
+        if !pages.is_empty() {
+            writeln!(out, "<h2>Requests outside any page</h2>").unwrap();
+        }
+        let waterfall = Waterfall::from_entries(orphans.iter().map(|&(_, entry)| entry));
+        writeln!(out, "<div class=\"waterfall\">{}</div>", waterfall.to_svg()).unwrap();
+        write_table(&mut out, index, &orphans);
+    }
+
+    writeln!(out, "<script>\n{}</script>\n</body>\n</html>", SCRIPT).unwrap();
+    out
+}
+
+fn write_table(out: &mut String, table: usize, entries: &[(usize, &Entry)]) {
+    writeln!(out, "<table class=\"entries\" id=\"entries-{}\">", table).unwrap();
+    writeln!(out, "<thead><tr><th>#</th><th>Method</th><th>URL</th><th>Status</th>\
+                   <th>Type</th><th>Size</th><th>Time</th></tr></thead>\n<tbody>").unwrap();
+    for &(index, entry) in entries {
+        let request = &entry.request;
+        let response = &entry.response;
+        let class = if response.status == 0 || response.status >= 400 {
+            "entry error"
+        } else {
+            "entry"
+        };
+        let time = entry.timings.total();
+        writeln!(out, "<tr class=\"{}\"><td data-value=\"{}\">{}</td>\
+                       <td data-value=\"{}\">{}</td><td class=\"url\" data-value=\"{}\">{}</td>\
+                       <td data-value=\"{}\">{}</td><td data-value=\"{}\">{}</td>\
+                       <td data-value=\"{}\">{}</td><td data-value=\"{}\">{} ms</td></tr>",
+                 class, index, index,
+                 escape(&request.method), escape(&request.method),
+                 escape(&request.url), escape(&request.url),
+                 response.status, response.status,
+                 escape(&response.content.mime_type), escape(&response.content.mime_type),
+                 response.content.size, response.content.size,
+                 time, time).unwrap();
+
+        writeln!(out, "<tr class=\"details\"><td colspan=\"7\">").unwrap();
+        write_headers(out, "Request headers", &request.headers);
+        if let Some(ref post_data) = request.post_data {
+            writeln!(out, "<h4>Request body ({})</h4>", escape(&post_data.mime_type)).unwrap();
+            write_text(out, &post_data.text);
+        }
+        write_headers(out, "Response headers", &response.headers);
+        write_content(out, &response.content);
+        writeln!(out, "</td></tr>").unwrap();
+    }
+    writeln!(out, "</tbody>\n</table>").unwrap();
+}
+
+fn write_headers(out: &mut String, heading: &str, headers: &[Header]) {
+    writeln!(out, "<h4>{}</h4>\n<pre>", heading).unwrap();
+    for header in headers {
+        writeln!(out, "{}: {}", escape(&header.name), escape(&header.value)).unwrap();
+    }
+    writeln!(out, "</pre>").unwrap();
+}
+
+fn write_text(out: &mut String, text: &str) {
+    if text.len() > MAX_BODY_SHOWN {
+        let mut end = MAX_BODY_SHOWN;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        writeln!(out, "<pre>{}</pre>\n<p>({} more bytes not shown)</p>",
+                 escape(&text[..end]), text.len() - end).unwrap();
+    } else {
+        writeln!(out, "<pre>{}</pre>", escape(text)).unwrap();
+    }
+}
+
+/// Shows the response body: text as text, images inline, and other binary bodies by size.
+fn write_content(out: &mut String, content: &Content) {
+    writeln!(out, "<h4>Response body ({})</h4>", escape(&content.mime_type)).unwrap();
+    let body = match content.body_bytes() {
+        Ok(body) => body,
+        Err(err) => {
+            writeln!(out, "<p>{}</p>", escape(&err.to_string())).unwrap();
+            return;
+        }
+    };
+    if body.is_empty() {
+        writeln!(out, "<p>(empty)</p>").unwrap();
+        return;
+    }
+    let is_image = content.mime_type.starts_with("image/");
+    match (is_image, &content.encoding, &content.text) {
+        (true, Some(_), Some(text)) if text.len() <= MAX_BODY_SHOWN => {
+            writeln!(out, "<img src=\"data:{};base64,{}\" alt=\"response body\">",
+                     escape(&content.mime_type), escape(text)).unwrap();
+        }
+        _ => match String::from_utf8(body) {
+            Ok(text) => write_text(out, &text),
+            Err(err) => {
+                writeln!(out, "<p>(binary, {} bytes)</p>", err.as_bytes().len()).unwrap();
+            }
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use report;
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Header;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Page;
+    use PageTimings;
+    use PostData;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(pageref: Option<&str>, url: &str, status: i32, content: Content) -> Entry {
+        Entry::new(
+            pageref.map(|pageref| pageref.to_string()),
+            "2009-04-16T12:07:20.000Z".to_string(),
+            Request::new("POST".to_string(), url.to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                         vec![Header::new("Accept".to_string(), "<any>".to_string(), None)],
+                         Vec::new(),
+                         Some(PostData::new("text/plain".to_string(), Vec::new(),
+                                            "a & b".to_string(), None)),
+                         None, None, None),
+            Response::new(status, "".to_string(), "HTTP/1.1".to_string(), Vec::new(), Vec::new(),
+                          content, "".to_string(), None, None, None),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable, None),
+            None,
+            None,
+            None
+        )
+    }
+
+    fn log() -> Log {
+        let mut log = Log::new(None, None);
+        log.add_page(Page::new("2009-04-16T12:07:20.000Z".to_string(), "page_0".to_string(),
+                               "Home <page>".to_string(),
+                               PageTimings::new(NotApplicable, NotApplicable, None), None));
+        log.add_entry(entry(Some("page_0"), "http://example.com/?a=1&b=2", 200,
+                            Content::new(5, None, "text/plain".to_string(),
+                                         Some("aGVsbG8=".to_string()),
+                                         Some("base64".to_string()), None)));
+        log.add_entry(entry(Some("page_0"), "http://example.com/pixel.png", 200,
+                            Content::new(3, None, "image/png".to_string(),
+                                         Some("AP8K".to_string()), Some("base64".to_string()),
+                                         None)));
+        log.add_entry(entry(None, "http://example.com/beacon", 503,
+                            Content::new(3, None, "application/octet-stream".to_string(),
+                                         Some("AP8K".to_string()), Some("base64".to_string()),
+                                         None)));
+        log
+    }
+
+    #[test]
+    fn test_html_report() {
+        let html = report::html(&log(), "Run <42>");
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.ends_with("</html>\n"));
+        assert!(html.contains("<title>Run &lt;42&gt;</title>"));
+        assert!(html.contains("<h2>Home &lt;page&gt; <small>(page_0, started \
+                               2009-04-16T12:07:20.000Z)</small></h2>"));
+        assert!(html.contains("<h2>Requests outside any page</h2>"));
+        assert_eq!(html.matches("<svg").count(), 2);
+        assert_eq!(html.matches("<tr class=\"entry\">").count(), 2);
+        assert_eq!(html.matches("<tr class=\"entry error\">").count(), 1);
+        assert!(html.contains("http://example.com/?a=1&amp;b=2"));
+        assert!(html.contains("Accept: &lt;any&gt;"));
+        assert!(html.contains("<pre>a &amp; b</pre>"));
+        assert!(html.contains("<pre>hello</pre>"));
+        assert!(html.contains("<img src=\"data:image/png;base64,AP8K\""));
+        assert!(html.contains("<p>(binary, 3 bytes)</p>"));
+
+        // Nothing is loaded from elsewhere.
+        assert!(!html.contains(" src=\"http"));
+        assert!(!html.contains(" href="));
+    }
+
+    #[test]
+    fn test_html_report_without_pages() {
+        let html = report::html(&Log::new(None, None), "empty");
+        assert!(!html.contains("<h2>"));
+        assert!(!html.contains("<table"));
+
+        let mut log = log();
+        log.pages = None;
+        let html = report::html(&log, "no pages");
+        assert!(!html.contains("<h2>"));
+        assert_eq!(html.matches("<svg").count(), 1);
+        assert_eq!(html.matches("<tr class=\"entry").count(), 3);
+    }
+}
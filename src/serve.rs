@@ -0,0 +1,545 @@
+//! A local HTTP server that answers requests with the responses recorded in a `Log`.
+//!
+//! Incoming requests are matched against the log's entries with a `Matcher`. The default matcher
+//! compares method, path, query and body and ignores the host, so a capture of any site can be
+//! served from localhost.
+//!
+//! Replayed responses are sent immediately unless the server is told to honour the recorded
+//! latency, in which case each entry's `wait` delays the first byte and its `receive` spreads out
+//! the body.
+//!
+//! A server created from a `Cassette` can also record: depending on the cassette's mode, requests
+//! are forwarded to an upstream server and the new entries are saved to the cassette file.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_json;
+
+use cassette::{Cassette, Mode};
+use cookie;
+use http::{self, WireRequest};
+use matcher::{Matcher, NoMatch};
+use Content;
+use Cookie;
+use Log;
+use Entry;
+use Request;
+use Response;
+
+/// Replayed bodies are throttled by writing them in pieces roughly this many milliseconds apart.
+const THROTTLE_INTERVAL_MS: f64 = 10.0;
+
+/// Headers describing the recorded transfer rather than the resource. The body is always served
+/// decoded, so these are recomputed (or dropped) for every response.
+const HOP_HEADERS: &[&str] = &[
+    "connection",
+    "content-encoding",
+    "content-length",
+    "keep-alive",
+    "transfer-encoding",
+];
+
+/// Serves the responses recorded in a `Log`.
+pub struct MockServer {
+    fallback: Response,
+    matcher: Matcher,
+    cassette: Option<Cassette>,
+    latency: Option<f64>,
+    state: Mutex<State>,
+}
+
+struct State {
+    log: Log,
+    /// How often each set of matching entries has been served, keyed by entry indices.
+    occurrences: HashMap<Vec<usize>, usize>,
+}
+
+impl MockServer {
+    /// Creates a server for `log`. Requests that match no entry are answered with `fallback`.
+    pub fn new(log: Log, fallback: Response) -> MockServer {
+        MockServer {
+            fallback,
+            matcher: Matcher::new(),
+            cassette: None,
+            latency: None,
+            state: Mutex::new(State {
+                log,
+                occurrences: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Creates a server replaying from and recording into `cassette`. Requests that are neither
+    /// replayed nor recorded are answered with `fallback`.
+    pub fn from_cassette(cassette: Cassette,
+                         fallback: Response) -> serde_json::Result<MockServer> {
+        let mut server = MockServer::new(cassette.load()?, fallback);
+        server.cassette = Some(cassette);
+        Ok(server)
+    }
+
+    /// Replaces the default `Matcher`.
+    pub fn with_matcher(mut self, matcher: Matcher) -> MockServer {
+        self.matcher = matcher;
+        self
+    }
+
+    /// Replays responses with their recorded latency, scaled by `factor`: 1.0 reproduces the
+    /// recorded timings, 0.5 halves them and 2.0 doubles them.
+    ///
+    /// The first byte of each response is delayed by the entry's `wait` time and the body is
+    /// throttled to arrive over its `receive` time. Negative factors count as 0; infinite and
+    /// NaN factors are an error.
+    pub fn with_latency(mut self, factor: f64) -> Result<MockServer, InvalidLatency> {
+        if !factor.is_finite() {
+            return Err(InvalidLatency { factor });
+        }
+        self.latency = Some(factor.max(0.0));
+        Ok(self)
+    }
+
+    /// Returns the recorded response for `request`.
+    ///
+    /// Requests matching the same entries count as repetitions of each other: the n-th
+    /// repetition is answered by the n-th matching entry, and by the last one once they run out.
+    pub fn respond(&self, request: &Request) -> Result<Response, NoMatch> {
+        self.replay(request).map(|entry| entry.response)
+    }
+
+    fn replay(&self, request: &Request) -> Result<Entry, NoMatch> {
+        let mut state = self.state.lock().unwrap();
+        let candidates = self.matcher.candidates(request, &state.log.entries)?;
+        let occurrence = state.occurrences.entry(candidates.clone()).or_insert(0);
+        let index = candidates[(*occurrence).min(candidates.len() - 1)];
+        *occurrence += 1;
+        Ok(state.log.entries[index].clone())
+    }
+
+    /// Accepts connections on `listener` until accepting fails, answering each connection on
+    /// its own thread.
+    pub fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let server = Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = server.clone();
+            thread::spawn(move || {
+                // A client hanging up mid-request only affects its own connection.
+                let _ = server.handle(stream);
+            });
+        }
+        Ok(())
+    }
+
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        while let Some(wire) = http::read_request(&mut reader)? {
+            let keep_alive = wire.keep_alive();
+            let (response, pacing) = self.answer(&wire);
+            write_response(&mut writer, &response, wire.method == "HEAD", keep_alive, pacing)?;
+            if !keep_alive {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks the response to `wire`, along with the pacing to send it with. Only replayed
+    /// responses are paced; recorded ones have just taken their real time upstream.
+    fn answer(&self, wire: &WireRequest) -> (Response, Option<Pacing>) {
+        let mode = self.cassette.as_ref().map_or(Mode::ReplayOnly, |cassette| cassette.mode());
+        if mode != Mode::ReRecordAll {
+            match self.replay(&wire.to_request()) {
+                Ok(entry) => {
+                    let pacing = self.latency.map(|factor| Pacing::new(&entry, factor));
+                    return (entry.response, pacing);
+                }
+                Err(_) if mode == Mode::ReplayOnly => return (self.fallback.clone(), None),
+                Err(_) => {}
+            }
+        }
+        match self.record(wire) {
+            Ok(response) => (response, None),
+            Err(err) => (bad_gateway(&err.to_string()), None),
+        }
+    }
+
+    fn record(&self, wire: &WireRequest) -> io::Result<Response> {
+        let cassette = self.cassette.as_ref().expect("recording without a cassette");
+        let entry = cassette.forward(wire)?;
+        let response = entry.response.clone();
+        // Saving under the lock keeps the file in the order entries were added.
+        let mut state = self.state.lock().unwrap();
+        state.log.add_entry(entry);
+        cassette.save(&state.log).map_err(|err| io::Error::other(err.to_string()))?;
+        Ok(response)
+    }
+}
+
+/// A latency factor that is infinite or NaN.
+#[derive(Debug)]
+pub struct InvalidLatency {
+    factor: f64,
+}
+
+impl fmt::Display for InvalidLatency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "latency factor must be finite, got {}", self.factor)
+    }
+}
+
+impl error::Error for InvalidLatency {}
+
+/// Recorded latency to reproduce when sending a response.
+struct Pacing {
+    /// Delay before the first byte.
+    wait: Duration,
+
+    /// Time over which the body is spread.
+    receive: Duration,
+}
+
+impl Pacing {
+    fn new(entry: &Entry, factor: f64) -> Pacing {
+        // Factors too large for a `Duration` wait as long as one can.
+        let scaled = |millis: u32| {
+            Duration::try_from_secs_f64(millis as f64 * factor / 1000.0).unwrap_or(Duration::MAX)
+        };
+        Pacing {
+            wait: scaled(entry.timings.wait),
+            receive: scaled(entry.timings.receive),
+        }
+    }
+}
+
+fn bad_gateway(message: &str) -> Response {
+    Response::new(
+        502,
+        "Bad Gateway".to_string(),
+        "HTTP/1.1".to_string(),
+        Vec::new(),
+        Vec::new(),
+        Content::new(message.len() as i32, None, "text/plain".to_string(),
+                     Some(message.to_string()), None, None),
+        "".to_string(),
+        None,
+        None,
+        None
+    )
+}
+
+/// The default fallback: an empty `404 Not Found`.
+pub fn not_found() -> Response {
+    Response::new(
+        404,
+        "Not Found".to_string(),
+        "HTTP/1.1".to_string(),
+        Vec::new(),
+        Vec::new(),
+        Content::new(0, None, "text/plain".to_string(), None, None, None),
+        "".to_string(),
+        None,
+        None,
+        None
+    )
+}
+
+/// Writes a recorded cookie as a `Set-Cookie` value. `date` is the response's `Date`.
+fn set_cookie_value(cookie: &Cookie, date: Option<DateTime<Utc>>) -> String {
+    // Recorded expiry dates have usually passed by the time a log is replayed, so replayed cookies
+    // are served as session cookies. Deletions are kept: a `Max-Age` of zero or less, or, without
+    // `Max-Age`, an `Expires` no later than the response was sent.
+    let mut cookie = cookie.clone();
+    let expires = cookie.expires.as_ref()
+        .and_then(|expires| DateTime::parse_from_rfc3339(expires).ok());
+    let deleted = match (cookie.max_age, expires, date) {
+        (Some(max_age), _, _) => max_age <= 0,
+        (None, Some(expires), Some(date)) => expires <= date,
+        _ => false,
+    };
+    cookie.expires = None;
+    cookie.max_age = if deleted { Some(0) } else { None };
+    cookie::set_cookie_header(&cookie)
+}
+
+fn write_response<W: Write>(writer: &mut W,
+                            response: &Response,
+                            head: bool,
+                            keep_alive: bool,
+                            pacing: Option<Pacing>) -> io::Result<()> {
+    let body = match response.content.body_bytes() {
+        Ok(body) => body,
+        Err(err) => {
+            let message = format!("recorded body cannot be served: {}", err);
+            let headers = vec![
+                ("Content-Type".to_string(), "text/plain".to_string()),
+                ("Content-Length".to_string(), message.len().to_string()),
+            ];
+            http::write_head(writer, 500, "Internal Server Error", &headers)?;
+            writer.write_all(message.as_bytes())?;
+            return writer.flush();
+        }
+    };
+
+    let mut headers: Vec<(String, String)> = response.headers
+        .iter()
+        .filter(|header| !HOP_HEADERS.iter().any(|hop| header.name.eq_ignore_ascii_case(hop)))
+        .map(|header| (header.name.clone(), header.value.clone()))
+        .collect();
+    let has_header = |headers: &[(String, String)], name: &str| {
+        http::find_header(headers, name).is_some()
+    };
+    if !has_header(&headers, "content-type") && !response.content.mime_type.is_empty() {
+        headers.push(("Content-Type".to_string(), response.content.mime_type.clone()));
+    }
+    if !has_header(&headers, "set-cookie") {
+        let date = response.headers().get("date").and_then(cookie::parse_http_date);
+        for cookie in &response.cookies {
+            headers.push(("Set-Cookie".to_string(), set_cookie_value(cookie, date)));
+        }
+    }
+    let has_body = !(response.status / 100 == 1 || response.status == 204 ||
+                     response.status == 304);
+    if has_body {
+        headers.push(("Content-Length".to_string(), body.len().to_string()));
+    }
+    if !keep_alive {
+        headers.push(("Connection".to_string(), "close".to_string()));
+    }
+
+    let pacing = match pacing {
+        Some(pacing) => pacing,
+        None => {
+            http::write_head(writer, response.status, &response.status_text, &headers)?;
+            if has_body && !head {
+                writer.write_all(&body)?;
+            }
+            return writer.flush();
+        }
+    };
+
+    thread::sleep(pacing.wait);
+    http::write_head(writer, response.status, &response.status_text, &headers)?;
+    writer.flush()?;
+    if !has_body || head || body.is_empty() {
+        return Ok(());
+    }
+    let pieces = (pacing.receive.as_secs_f64() * 1000.0 / THROTTLE_INTERVAL_MS) as usize;
+    let pieces = pieces.clamp(1, body.len());
+    let piece_len = body.len().div_ceil(pieces);
+    let interval = pacing.receive / pieces as u32;
+    for piece in body.chunks(piece_len) {
+        thread::sleep(interval);
+        writer.write_all(piece)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use cookie;
+    use serve::{self, MockServer};
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Cookie;
+    use Entry;
+    use Header;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(method: &str, url: &str, status: i32, content: Content) -> Entry {
+        Entry::new(
+            None,
+            "2009-04-16T12:07:23.596Z".to_string(),
+            Request::new(method.to_string(), url.to_string(), "HTTP/1.1".to_string(),
+                         Vec::new(), Vec::new(), Vec::new(), None, None, None, None),
+            Response::new(
+                status,
+                "OK".to_string(),
+                "HTTP/1.1".to_string(),
+                vec![Cookie::new("session".to_string(), "abc".to_string(),
+                                 Some("/".to_string()), None, None, Some(true), None, None)],
+                vec![Header::new("Content-Encoding".to_string(), "gzip".to_string(), None),
+                     Header::new("X-Recorded".to_string(), "yes".to_string(), None)],
+                content,
+                "".to_string(),
+                None,
+                None,
+                None
+            ),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable, None),
+            None,
+            None,
+            None
+        )
+    }
+
+    fn start(log: Log) -> SocketAddr {
+        start_server(MockServer::new(log, serve::not_found()))
+    }
+
+    fn start_server(server: MockServer) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || server.serve(listener));
+        addr
+    }
+
+    fn fetch(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_set_cookie_value() {
+        let date = cookie::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT");
+        let cookie = |value: &str, expires: Option<&str>, max_age: Option<i64>| Cookie {
+            expires: expires.map(str::to_string),
+            max_age,
+            ..Cookie::new("a".to_string(), value.to_string(), Some("/".to_string()), None, None,
+                          None, None, None)
+        };
+        let future = Some("2015-10-22T07:28:00.000Z");
+        let past = Some("1970-01-01T00:00:00.000Z");
+        assert_eq!(serve::set_cookie_value(&cookie("1", future, Some(3600)), date),
+                   "a=1; Path=/");
+        assert_eq!(serve::set_cookie_value(&cookie("1", future, None), date), "a=1; Path=/");
+        assert_eq!(serve::set_cookie_value(&cookie("", None, Some(0)), date),
+                   "a=; Path=/; Max-Age=0");
+        assert_eq!(serve::set_cookie_value(&cookie("", future, Some(-1)), date),
+                   "a=; Path=/; Max-Age=0");
+        assert_eq!(serve::set_cookie_value(&cookie("", past, None), date),
+                   "a=; Path=/; Max-Age=0");
+        assert_eq!(serve::set_cookie_value(&cookie("", past, None), None), "a=; Path=/");
+    }
+
+    #[test]
+    fn test_serve_recorded_response() {
+        let mut log = Log::new(None, None);
+        log.add_entry(entry(
+            "GET",
+            "http://www.example.com/path/?param=value",
+            200,
+            Content::new(5, None, "text/plain".to_string(), Some("hello".to_string()), None, None)
+        ));
+        let addr = start(log);
+
+        let response = fetch(addr, "GET /path/?param=value HTTP/1.1\r\n\
+                                    Host: localhost\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("X-Recorded: yes\r\n"));
+        assert!(response.contains("Content-Type: text/plain\r\n"));
+        assert!(response.contains("Set-Cookie: session=abc; Path=/; HttpOnly\r\n"));
+        assert!(response.contains("Content-Length: 5\r\n"));
+        assert!(!response.contains("Content-Encoding"));
+        assert!(response.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn test_serve_base64_body() {
+        let mut log = Log::new(None, None);
+        log.add_entry(entry(
+            "GET",
+            "http://www.example.com/data",
+            200,
+            Content::new(5, None, "text/plain".to_string(), Some("aGVsbG8=".to_string()),
+                         Some("base64".to_string()), None)
+        ));
+        let addr = start(log);
+
+        let response = fetch(addr, "GET /data HTTP/1.0\r\n\r\n");
+        assert!(response.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn test_serve_repeated_requests_in_order() {
+        let mut log = Log::new(None, None);
+        for text in &["first", "second"] {
+            log.add_entry(entry(
+                "GET",
+                "http://www.example.com/poll",
+                200,
+                Content::new(0, None, "text/plain".to_string(), Some(text.to_string()), None, None)
+            ));
+        }
+        let addr = start(log);
+
+        let request = "GET /poll HTTP/1.1\r\nConnection: close\r\n\r\n";
+        assert!(fetch(addr, request).ends_with("first"));
+        assert!(fetch(addr, request).ends_with("second"));
+        assert!(fetch(addr, request).ends_with("second"));
+    }
+
+    #[test]
+    fn test_serve_with_latency() {
+        // The helper records wait = 2ms and receive = 3ms.
+        let mut log = Log::new(None, None);
+        log.add_entry(entry(
+            "GET",
+            "http://www.example.com/slow",
+            200,
+            Content::new(5, None, "text/plain".to_string(), Some("hello".to_string()), None, None)
+        ));
+        let request = "GET /slow HTTP/1.1\r\nConnection: close\r\n\r\n";
+
+        let addr = start_server(MockServer::new(log.clone(), serve::not_found())
+                                    .with_latency(100.0).unwrap());
+        let started = Instant::now();
+        assert!(fetch(addr, request).ends_with("\r\n\r\nhello"));
+        assert!(started.elapsed() >= Duration::from_millis(500));
+
+        let addr = start_server(MockServer::new(log, serve::not_found())
+                                    .with_latency(0.0).unwrap());
+        let started = Instant::now();
+        assert!(fetch(addr, request).ends_with("\r\n\r\nhello"));
+        assert!(started.elapsed() < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_infinite_latency() {
+        let error = MockServer::new(Log::new(None, None), serve::not_found())
+            .with_latency(f64::INFINITY).err().unwrap();
+        assert_eq!(error.to_string(), "latency factor must be finite, got inf");
+        assert!(MockServer::new(Log::new(None, None), serve::not_found())
+                    .with_latency(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_serve_fallback() {
+        let mut log = Log::new(None, None);
+        log.add_entry(entry(
+            "POST",
+            "http://www.example.com/path/",
+            201,
+            Content::new(0, None, "text/plain".to_string(), None, None, None)
+        ));
+        let addr = start(log);
+
+        let response = fetch(addr, "GET /path/ HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
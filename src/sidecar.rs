@@ -0,0 +1,347 @@
+//! Keeping bodies outside the log.
+//!
+//! Embedded bodies make large logs unreadable in an editor and noisy to diff. `extract` moves
+//! every response body and post data text into a sidecar, either a directory or a `.zip`
+//! archive, as a file named by the SHA-256 hash of the body. The body is replaced by that name in
+//! the custom `_file` field, so identical bodies share a file. `inline` moves them back, checking
+//! each file against the hash in its name.
+//!
+//! Response bodies are stored as the bytes the browser operated on, so base64 encoded bodies are
+//! decoded on the way out and encoded again on the way back in. Bodies with an encoding this
+//! crate does not understand stay in the log.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use base64;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use Log;
+
+/// Sidecar files by name.
+type Files = BTreeMap<String, Vec<u8>>;
+
+/// Moves the bodies of `log` into the sidecar at `path`, returning how many were moved.
+///
+/// A path ending in `.zip` is written as a new zip archive; any other path is used as a
+/// directory, created if needed, to which missing files are added. Empty bodies and bodies that
+/// are already extracted are left alone. Bodies shared by `Log::dedup_bodies` are extracted too
+/// and leave the log's table, so `inline` embeds them in each entry again. The sidecar is
+/// written before the log is changed, so if it cannot be written the log is left as it was.
+pub fn extract<P: AsRef<Path>>(log: &mut Log, path: P) -> io::Result<usize> {
+    let path = path.as_ref();
+    let mut files = Files::new();
+    // The names of the post data and response bodies to move, by entry.
+    let mut names: Vec<(Option<String>, Option<String>)> = Vec::new();
+    for entry in &log.entries {
+        let post = entry.request.post_data.as_ref()
+            .filter(|post_data| post_data.file.is_none() && !post_data.text.is_empty())
+            .map(|post_data| add(&mut files, post_data.text.as_bytes().to_vec()));
+
+        let content = &entry.response.content;
+        let empty = content.text.as_ref().is_none_or(|text| text.is_empty()) &&
+            content.shared.is_none();
+        let response = if content.file.is_some() || empty {
+            None
+        } else {
+            content.body_bytes().ok().map(|body| add(&mut files, body))
+        };
+        names.push((post, response));
+    }
+
+    if is_zip(path) {
+        write_zip(path, &files)?;
+    } else {
+        write_dir(path, &files)?;
+    }
+
+    let mut count = 0;
+    for (entry, (post, response)) in log.entries.iter_mut().zip(names) {
+        if let (Some(name), Some(post_data)) = (post, entry.request.post_data.as_mut()) {
+            post_data.text.clear();
+            post_data.file = Some(name);
+            count += 1;
+        }
+        if let Some(name) = response {
+            let content = &mut entry.response.content;
+            if let Some(shared) = content.shared.take() {
+                content.encoding = shared.encoding.clone();
+            }
+            content.body = None;
+            content.text = None;
+            content.file = Some(name);
+            count += 1;
+        }
+    }
+    let entries = &log.entries;
+    log.bodies.retain(|hash, _| {
+        entries.iter().any(|entry| entry.response.content.body.as_ref() == Some(hash))
+    });
+    Ok(count)
+}
+
+/// Moves the bodies extracted from `log` back into it from the sidecar at `path`, returning how
+/// many were moved.
+///
+/// Every file is read and checked against its name before the log is changed, so a missing or
+/// modified file leaves the log as it was.
+pub fn inline<P: AsRef<Path>>(log: &mut Log, path: P) -> io::Result<usize> {
+    let path = path.as_ref();
+    let mut names: Vec<&str> = Vec::new();
+    let mut post_names: Vec<&str> = Vec::new();
+    for entry in &log.entries {
+        if let Some(name) = entry.request.post_data.as_ref().and_then(|data| data.file.as_ref()) {
+            names.push(name);
+            post_names.push(name);
+        }
+        if let Some(ref name) = entry.response.content.file {
+            names.push(name);
+        }
+    }
+
+    let mut files = Files::new();
+    let mut archive = if is_zip(path) {
+        Some(ZipArchive::new(File::open(path)?)?)
+    } else {
+        None
+    };
+    for name in names {
+        if files.contains_key(name) {
+            continue;
+        }
+        if !is_file_name(name) {
+            return Err(invalid_data(format!("\"{}\" is not a body file name", name)));
+        }
+        let mut body = Vec::new();
+        match archive {
+            Some(ref mut archive) => {
+                let mut file = archive.by_name(name).map_err(|_| {
+                    io::Error::new(io::ErrorKind::NotFound,
+                                   format!("{} has no file {}", path.display(), name))
+                })?;
+                file.read_to_end(&mut body)?;
+            }
+            None => {
+                File::open(path.join(name))?.read_to_end(&mut body)?;
+            }
+        }
+        if hash(&body) != name {
+            return Err(invalid_data(format!("body file {} does not match its hash", name)));
+        }
+        files.insert(name.to_string(), body);
+    }
+    for name in post_names {
+        if std::str::from_utf8(&files[name]).is_err() {
+            return Err(invalid_data(format!("body file {} is not UTF-8 post data", name)));
+        }
+    }
+    Ok(put_back(log, &files))
+}
+
+/// Adds `body` to `files`, returning its name.
+fn add(files: &mut Files, body: Vec<u8>) -> String {
+    let name = hash(&body);
+    files.entry(name.clone()).or_insert(body);
+    name
+}
+
+/// Replaces every `_file` reference in `log` with the body from `files`, which must hold them
+/// all, with UTF-8 post data.
+fn put_back(log: &mut Log, files: &Files) -> usize {
+    let mut count = 0;
+    for entry in &mut log.entries {
+        if let Some(ref mut post_data) = entry.request.post_data {
+            if let Some(name) = post_data.file.take() {
+                post_data.text = String::from_utf8_lossy(&files[&name]).into_owned();
+                count += 1;
+            }
+        }
+
+        let content = &mut entry.response.content;
+        if let Some(name) = content.file.take() {
+            let body = &files[&name];
+            let text = match (content.encoding.is_some(), String::from_utf8(body.clone())) {
+                (false, Ok(text)) => text,
+                _ => {
+                    content.encoding = Some("base64".to_string());
+                    base64::engine::general_purpose::STANDARD.encode(body)
+                }
+            };
+            content.text = Some(text);
+            count += 1;
+        }
+    }
+    count
+}
+
+fn write_dir(path: &Path, files: &Files) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    for (name, body) in files {
+        let file = path.join(name);
+        if !file.exists() {
+            fs::write(file, body)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_zip(path: &Path, files: &Files) -> io::Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    for (name, body) in files {
+        zip.start_file(name.as_str(), FileOptions::default())?;
+        zip.write_all(body)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// The SHA-256 hash of `body` in lowercase hexadecimal, which names its sidecar file.
+pub(crate) fn hash(body: &[u8]) -> String {
+    let mut name = String::with_capacity(64);
+    for byte in Sha256::digest(body) {
+        write!(name, "{:02x}", byte).unwrap();
+    }
+    name
+}
+
+/// Whether `name` could have come from `hash`, and so is safe to join to a directory.
+fn is_file_name(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use sidecar;
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use PostData;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn log() -> Log {
+        let mut log = Log::new(None, None);
+        let bodies = [
+            ("a=1", Content::new(5, None, "text/plain".to_string(), Some("hello".to_string()),
+                                 None, None)),
+            ("", Content::new(3, None, "image/png".to_string(), Some("AP8K".to_string()),
+                              Some("base64".to_string()), None)),
+            ("a=1", Content::new(5, None, "text/plain".to_string(), Some("hello".to_string()),
+                                 None, None)),
+            ("", Content::new(0, None, "text/plain".to_string(), None, None, None)),
+        ];
+        for &(post, ref content) in &bodies {
+            let post_data = PostData::new("application/x-www-form-urlencoded".to_string(),
+                                          Vec::new(), post.to_string(), None);
+            log.add_entry(Entry::new(
+                None,
+                "2009-04-16T12:07:20.000Z".to_string(),
+                Request::new("POST".to_string(), "http://example.com/".to_string(),
+                             "HTTP/1.1".to_string(), Vec::new(), Vec::new(), Vec::new(),
+                             Some(post_data), None, None, None),
+                Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                              Vec::new(), content.clone(), "".to_string(), None, None, None),
+                Cache::new(Absent, Absent, None),
+                Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable,
+                            None),
+                None,
+                None,
+                None
+            ));
+        }
+        log
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for name in &["dir", "zip"] {
+            let path = env::temp_dir()
+                .join(format!("har-sidecar-{}-{}.{}", name, process::id(), name));
+            let original = log();
+            let mut log = original.clone();
+            assert_eq!(sidecar::extract(&mut log, &path).unwrap(), 5);
+
+            let content = &log.entries[1].response.content;
+            assert_eq!(content.text, None);
+            assert_eq!(content.file.as_ref().unwrap(),
+                       "712450d3c4a79eea9509e75dc1dacdeff58034df538536cfae2da882bd8a0c50");
+            assert_eq!(log.entries[0].request.post_data.as_ref().unwrap().text, "");
+            assert_eq!(log.entries[0].response.content.file,
+                       log.entries[2].response.content.file);
+
+            assert_eq!(sidecar::inline(&mut log, &path).unwrap(), 5);
+            assert_eq!(log, original);
+
+            if path.is_dir() {
+                fs::remove_dir_all(&path).unwrap();
+            } else {
+                fs::remove_file(&path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_inline_checks_hash() {
+        let path = env::temp_dir().join(format!("har-sidecar-tampered-{}", process::id()));
+        let mut log = log();
+        sidecar::extract(&mut log, &path).unwrap();
+        let name = log.entries[1].response.content.file.clone().unwrap();
+        fs::write(path.join(&name), b"tampered").unwrap();
+
+        let extracted = log.clone();
+        let err = sidecar::inline(&mut log, &path).unwrap_err();
+        assert_eq!(err.to_string(), format!("body file {} does not match its hash", name));
+        assert_eq!(log, extracted);
+
+        // Post data is checked before anything is put back, wherever it is in the log.
+        fs::write(path.join(&name), b"\x00\xff\x0a").unwrap();
+        let binary = sidecar::hash(b"\xff");
+        fs::write(path.join(&binary), b"\xff").unwrap();
+        log.entries[2].request.post_data.as_mut().unwrap().file = Some(binary.clone());
+        let extracted = log.clone();
+        let err = sidecar::inline(&mut log, &path).unwrap_err();
+        assert_eq!(err.to_string(), format!("body file {} is not UTF-8 post data", binary));
+        assert_eq!(log, extracted);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_extract_shared_bodies() {
+        let path = env::temp_dir().join(format!("har-sidecar-shared-{}", process::id()));
+        let mut log = log();
+        assert_eq!(log.dedup_bodies(), 1);
+        assert_eq!(sidecar::extract(&mut log, &path).unwrap(), 5);
+        assert!(log.bodies.is_empty());
+        assert_eq!(log.entries[0].response.content.body, None);
+        assert_eq!(log.entries[0].response.content.file,
+                   log.entries[2].response.content.file);
+
+        assert_eq!(sidecar::inline(&mut log, &path).unwrap(), 5);
+        assert_eq!(log, self::log());
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+//! Computing `headersSize` and `bodySize` from the rest of an entry.
+//!
+//! The header size is the length of the HTTP/1.x message head as it would be written from the
+//! model: the request or status line, one `Name: value` line per header and the empty line that
+//! ends the block, each terminated by CRLF. HTTP/2 and HTTP/3 send headers as compressed binary
+//! frames, so there is no such block; for them, and for any version that is not HTTP/1.x, the
+//! header size is not applicable and `fill_sizes` records -1, the HAR value for "not
+//! available".
+//!
+//! The body size is the number of bytes on the wire, which is the same for every version. It
+//! is read from the decoded body unless a `Content-Encoding` says the body was compressed, in
+//! which case `content.compression` or the `Content-Length` header is used instead, since
+//! compressing the body again would not give back the original bytes.
+
+use std::convert::TryFrom;
+
+use url::{Position, Url};
+
+use form;
+use Entry;
+use Log;
+use PostData;
+use Request;
+use Response;
+
+/// Whether `version` is HTTP/1.0 or HTTP/1.1, in any case. Browsers write both `HTTP/1.1` and
+/// `http/1.1`.
+pub fn is_http1(version: &str) -> bool {
+    version.len() > 7 && version[..7].eq_ignore_ascii_case("http/1.")
+}
+
+/// The length of a message head with the given start line and headers.
+fn head_size<'a, I: Iterator<Item = (&'a str, &'a str)>>(start_line: &str, headers: I) -> usize {
+    let headers: usize = headers.map(|(name, value)| name.len() + 2 + value.len() + 2).sum();
+    start_line.len() + 2 + headers + 2
+}
+
+/// Whether `encoding`, the value of a `Content-Encoding` header, changes the body.
+fn is_encoded(encoding: Option<&str>) -> bool {
+    encoding.is_some_and(|encoding| {
+        encoding.split(',').any(|coding| {
+            let coding = coding.trim();
+            !coding.is_empty() && !coding.eq_ignore_ascii_case("identity")
+        })
+    })
+}
+
+fn to_i32(size: usize) -> Option<i32> {
+    i32::try_from(size).ok()
+}
+
+/// The bytes of the body `post_data` stands for. Params are encoded when there is no text; a
+/// text extracted to a sidecar file has an unknown size.
+fn post_data_size(post_data: &PostData) -> Option<usize> {
+    if !post_data.text.is_empty() || post_data.params.is_empty() {
+        return if post_data.file.is_some() { None } else { Some(post_data.text.len()) };
+    }
+    if form::is_type(&post_data.mime_type, "application/x-www-form-urlencoded") {
+        Some(form::encode_urlencoded(&post_data.params).len())
+    } else if form::is_type(&post_data.mime_type, "multipart/form-data") {
+        form::boundary(&post_data.mime_type)
+            .map(|boundary| form::encode_multipart(&post_data.params, &boundary).len())
+    } else {
+        None
+    }
+}
+
+impl Request {
+    /// The size of the HTTP/1.x request head, or `None` for other versions. The target is
+    /// written in origin form (path and query), or in authority form for `CONNECT`; a URL that
+    /// does not parse is written as recorded.
+    pub fn compute_headers_size(&self) -> Option<i32> {
+        if !is_http1(&self.http_version) {
+            return None;
+        }
+        let target = match Url::parse(&self.url) {
+            Ok(ref url) if self.method.eq_ignore_ascii_case("connect") => {
+                format!("{}:{}", url.host_str().unwrap_or(""),
+                        url.port_or_known_default().unwrap_or(443))
+            }
+            Ok(ref url) => url[Position::BeforePath..Position::AfterQuery].to_string(),
+            Err(_) => self.url.clone(),
+        };
+        let start_line = format!("{} {} {}", self.method, target, self.http_version);
+        let headers = self.headers.iter().map(|header| (&*header.name, &*header.value));
+        to_i32(head_size(&start_line, headers))
+    }
+
+    /// The size of the request body, or `None` if it cannot be told from the model.
+    pub fn compute_body_size(&self) -> Option<i32> {
+        if is_encoded(self.headers().get("content-encoding")) {
+            return self.headers().content_length().and_then(|length| i32::try_from(length).ok());
+        }
+        match self.post_data {
+            Some(ref post_data) => post_data_size(post_data).and_then(to_i32),
+            None => Some(0),
+        }
+    }
+}
+
+impl Response {
+    /// The size of the HTTP/1.x status line and headers, or `None` for other versions.
+    pub fn compute_headers_size(&self) -> Option<i32> {
+        if !is_http1(&self.http_version) {
+            return None;
+        }
+        let start_line = format!("{} {} {}", self.http_version, self.status, self.status_text);
+        let headers = self.headers.iter().map(|header| (&*header.name, &*header.value));
+        to_i32(head_size(&start_line, headers))
+    }
+
+    /// The size of the response body as received, or `None` if it cannot be told from the
+    /// model. Informational, 204 and 304 responses have no body.
+    pub fn compute_body_size(&self) -> Option<i32> {
+        if (100..200).contains(&self.status) || self.status == 204 || self.status == 304 {
+            return Some(0);
+        }
+        let content = &self.content;
+        if is_encoded(self.headers().get("content-encoding")) {
+            // A compression outside 0..=size contradicts the size, so the header is used instead.
+            return match content.compression {
+                Some(compression) if 0 <= compression && compression <= content.size => {
+                    content.size.checked_sub(compression)
+                }
+                _ => self.headers().content_length().and_then(|length| i32::try_from(length).ok()),
+            };
+        }
+        if content.text.is_some() || content.shared.is_some() {
+            content.body_bytes().ok().and_then(|body| to_i32(body.len()))
+        } else if content.size >= 0 && content.file.is_none() {
+            Some(content.size)
+        } else {
+            None
+        }
+    }
+}
+
+impl Entry {
+    /// Fills the missing `headersSize` and `bodySize` of the request and response, writing -1
+    /// where the size is not applicable or cannot be computed. Recorded sizes are kept.
+    pub fn fill_sizes(&mut self) {
+        let request = &mut self.request;
+        if request.headers_size.is_none() {
+            request.headers_size = Some(request.compute_headers_size().unwrap_or(-1));
+        }
+        if request.body_size.is_none() {
+            request.body_size = Some(request.compute_body_size().unwrap_or(-1));
+        }
+        let response = &mut self.response;
+        if response.headers_size.is_none() {
+            response.headers_size = Some(response.compute_headers_size().unwrap_or(-1));
+        }
+        if response.body_size.is_none() {
+            response.body_size = Some(response.compute_body_size().unwrap_or(-1));
+        }
+    }
+}
+
+impl Log {
+    /// Fills the missing sizes of every entry, see `Entry::fill_sizes`.
+    pub fn fill_sizes(&mut self) {
+        for entry in &mut self.entries {
+            entry.fill_sizes();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Header;
+    use OptionalTiming::NotApplicable;
+    use Param;
+    use PostData;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn header(name: &str, value: &str) -> Header {
+        Header::new(name.to_string(), value.to_string(), None)
+    }
+
+    fn response(version: &str, status: i32, headers: Vec<Header>, content: Content) -> Response {
+        Response::new(status, "OK".to_string(), version.to_string(), Vec::new(), headers, content,
+                      "".to_string(), None, None, None)
+    }
+
+    #[test]
+    fn test_request_sizes() {
+        let mut request = Request::new(
+            "POST".to_string(), "https://example.com/search?q=1#top".to_string(),
+            "HTTP/1.1".to_string(), Vec::new(),
+            vec![header("Host", "example.com"), header("Content-Length", "7")], Vec::new(),
+            Some(PostData::new("application/x-www-form-urlencoded".to_string(), Vec::new(),
+                               "a=1&b=é".to_string(), None)),
+            None, None, None);
+        let head = "POST /search?q=1 HTTP/1.1\r\nHost: example.com\r\nContent-Length: 7\r\n\r\n";
+        assert_eq!(request.compute_headers_size(), Some(head.len() as i32));
+        assert_eq!(request.compute_body_size(), Some(8));
+
+        request.post_data = Some(PostData::new(
+            "application/x-www-form-urlencoded".to_string(),
+            vec![Param::new("q".to_string(), Some("a b".to_string()), None, None, None)],
+            "".to_string(), None));
+        assert_eq!(request.compute_body_size(), Some(5));
+
+        request.post_data = None;
+        request.http_version = "h2".to_string();
+        assert_eq!(request.compute_headers_size(), None);
+        assert_eq!(request.compute_body_size(), Some(0));
+    }
+
+    #[test]
+    fn test_response_sizes() {
+        let text = Content::new(5, None, "text/plain".to_string(), Some("hello".to_string()),
+                                None, None);
+        let response = response("HTTP/1.1", 200, vec![header("Content-Type", "text/plain")],
+                                text.clone());
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n";
+        assert_eq!(response.compute_headers_size(), Some(head.len() as i32));
+        assert_eq!(response.compute_body_size(), Some(5));
+
+        let gzipped = Content::new(1000, Some(700), "text/plain".to_string(),
+                                   Some("x".repeat(1000)), None, None);
+        let response = self::response("http/1.1", 200, vec![header("Content-Encoding", "gzip")],
+                                      gzipped);
+        assert_eq!(response.compute_body_size(), Some(300));
+
+        let bogus = Content::new(1000, Some(-2147483648), "text/plain".to_string(),
+                                 Some("x".repeat(1000)), None, None);
+        let response = self::response("HTTP/1.1", 200, vec![header("Content-Encoding", "gzip"),
+                                                             header("Content-Length", "42")],
+                                      bogus);
+        assert_eq!(response.compute_body_size(), Some(42));
+
+        let response = self::response("HTTP/1.1", 200,
+                                      vec![header("content-encoding", "br"),
+                                           header("Content-Length", "42")],
+                                      text.clone());
+        assert_eq!(response.compute_body_size(), Some(42));
+
+        let response = self::response("HTTP/1.1", 200, vec![header("Content-Encoding", "br")],
+                                      text.clone());
+        assert_eq!(response.compute_body_size(), None);
+
+        let response = self::response("HTTP/2.0", 304, Vec::new(), text);
+        assert_eq!(response.compute_headers_size(), None);
+        assert_eq!(response.compute_body_size(), Some(0));
+    }
+
+    #[test]
+    fn test_fill_sizes() {
+        let request = Request::new("GET".to_string(), "http://example.com/".to_string(),
+                                   "h3".to_string(), Vec::new(), Vec::new(), Vec::new(), None,
+                                   None, Some(12), None);
+        let response = response("h3", 200, Vec::new(),
+                                Content::new(2, None, "text/plain".to_string(),
+                                             Some("hi".to_string()), None, None));
+        let mut entry = Entry::new(None, "2009-04-16T12:07:20.000Z".to_string(), request,
+                                   response, Cache::new(Absent, Absent, None),
+                                   Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2,
+                                               3, NotApplicable, None),
+                                   None, None, None);
+        entry.fill_sizes();
+        assert_eq!(entry.request.headers_size, Some(-1));
+        assert_eq!(entry.request.body_size, Some(12));
+        assert_eq!(entry.response.headers_size, Some(-1));
+        assert_eq!(entry.response.body_size, Some(2));
+    }
+}
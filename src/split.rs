@@ -0,0 +1,214 @@
+//! Cutting a log into several smaller logs.
+//!
+//! Every part keeps the original `creator`, `browser` and comment, and only the pages its
+//! entries refer to. Entries keep their original order.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::time::Duration;
+
+use url::Url;
+
+use Entry;
+use Log;
+
+/// Splits `log` into one log per page, keyed by page id, in page order.
+///
+/// Entries without a `pageref`, or referring to a page that does not exist, are left out.
+pub fn by_page(log: &Log) -> Vec<(String, Log)> {
+    let pages = match log.pages {
+        Some(ref pages) => pages,
+        None => return Vec::new(),
+    };
+    pages.iter()
+        .map(|page| {
+            let entries = log.entries
+                .iter()
+                .filter(|entry| entry.pageref.as_ref() == Some(&page.id))
+                .collect();
+            (page.id.clone(), log.subset(entries))
+        })
+        .collect()
+}
+
+/// Splits `log` into one log per server host, in order of first appearance.
+///
+/// Entries whose URL has no host are grouped under the empty string.
+pub fn by_host(log: &Log) -> Vec<(String, Log)> {
+    group(log, |entry| {
+        Some(Url::parse(&entry.request.url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_default())
+    })
+}
+
+/// Splits `log` into consecutive windows of length `window`, starting at the earliest entry.
+///
+/// Each part is keyed by the start of its window. Empty windows are skipped, as are entries
+/// whose `startedDateTime` cannot be parsed. Windows are counted in whole milliseconds, so a
+/// window shorter than a millisecond is an error.
+pub fn by_time_window(log: &Log, window: Duration) -> Result<Vec<(String, Log)>, InvalidWindow> {
+    let window = match i64::try_from(window.as_millis()) {
+        Ok(0) => return Err(InvalidWindow { window }),
+        Ok(window) => window,
+        Err(_) => i64::MAX,
+    };
+    let first = match log.entries.iter().filter_map(|entry| entry.started()).min() {
+        Some(first) => first,
+        None => return Ok(Vec::new()),
+    };
+    let mut parts = group(log, |entry| {
+        entry.started().map(|started| {
+            let index = (started - first).num_milliseconds() / window;
+            let start = first + chrono::Duration::milliseconds(index * window);
+            start.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+        })
+    });
+    parts.sort_by_key(|part| part.1.entries.iter().filter_map(|entry| entry.started()).min());
+    Ok(parts)
+}
+
+/// A time window too short to split by.
+#[derive(Debug)]
+pub struct InvalidWindow {
+    window: Duration,
+}
+
+impl fmt::Display for InvalidWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "time window must be at least 1ms, got {:?}", self.window)
+    }
+}
+
+impl error::Error for InvalidWindow {}
+
+/// Groups entries by `key`, leaving out entries without one, in order of first appearance.
+fn group<F>(log: &Log, key: F) -> Vec<(String, Log)>
+    where F: Fn(&Entry) -> Option<String>
+{
+    let mut keys = Vec::new();
+    let mut groups: HashMap<String, Vec<&Entry>> = HashMap::new();
+    for entry in &log.entries {
+        if let Some(key) = key(entry) {
+            if !groups.contains_key(&key) {
+                keys.push(key.clone());
+            }
+            groups.entry(key).or_default().push(entry);
+        }
+    }
+    keys.into_iter()
+        .map(|key| {
+            let entries = groups.remove(&key).unwrap();
+            (key, log.subset(entries))
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use std::time::Duration;
+
+    use split;
+    use Browser;
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Log;
+    use OptionalTiming::NotApplicable;
+    use Page;
+    use PageTimings;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(pageref: Option<&str>, started_date_time: &str, url: &str) -> Entry {
+        Entry::new(
+            pageref.map(|pageref| pageref.to_string()),
+            started_date_time.to_string(),
+            Request::new("GET".to_string(), url.to_string(), "HTTP/1.1".to_string(),
+                         Vec::new(), Vec::new(), Vec::new(), None, None, None, None),
+            Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(), Vec::new(),
+                          Content::new(0, None, "text/plain".to_string(), None, None, None),
+                          "".to_string(), None, None, None),
+            Cache::new(Absent, Absent, None),
+            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2, 3, NotApplicable, None),
+            None,
+            None,
+            None
+        )
+    }
+
+    fn log() -> Log {
+        let mut log = Log::new(Some(Browser::new("Firefox".to_string(), "3.6".to_string(), None)),
+                               Some("Comment".to_string()));
+        for id in &["page_0", "page_1"] {
+            log.add_page(Page::new("2009-04-16T12:07:20.000Z".to_string(), id.to_string(),
+                                   id.to_string(),
+                                   PageTimings::new(NotApplicable, NotApplicable, None), None));
+        }
+        log.add_entry(entry(Some("page_0"), "2009-04-16T12:07:20.000Z", "http://a.com/1"));
+        log.add_entry(entry(Some("page_1"), "2009-04-16T12:07:21.500Z", "http://b.com/2"));
+        log.add_entry(entry(None, "2009-04-16T12:07:25.000Z", "http://a.com/3"));
+        log.add_entry(entry(Some("page_1"), "2009-04-16T12:07:20.900Z", "http://a.com/4"));
+        log
+    }
+
+    fn urls(log: &Log) -> Vec<&str> {
+        log.entries().iter().map(|entry| entry.request.url.as_str()).collect()
+    }
+
+    fn page_ids(log: &Log) -> Vec<&str> {
+        log.pages.as_ref().unwrap().iter().map(|page| page.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_by_page() {
+        let original = log();
+        let parts = split::by_page(&original);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].0, "page_0");
+        assert_eq!(urls(&parts[0].1), vec!["http://a.com/1"]);
+        assert_eq!(page_ids(&parts[0].1), vec!["page_0"]);
+        assert_eq!(parts[1].0, "page_1");
+        assert_eq!(urls(&parts[1].1), vec!["http://b.com/2", "http://a.com/4"]);
+        assert_eq!(page_ids(&parts[1].1), vec!["page_1"]);
+        assert_eq!(parts[1].1.browser, original.browser);
+        assert_eq!(parts[1].1.creator, original.creator);
+        assert_eq!(parts[1].1.comment, original.comment);
+    }
+
+    #[test]
+    fn test_by_host() {
+        let parts = split::by_host(&log());
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].0, "a.com");
+        assert_eq!(urls(&parts[0].1), vec!["http://a.com/1", "http://a.com/3", "http://a.com/4"]);
+        assert_eq!(page_ids(&parts[0].1), vec!["page_0", "page_1"]);
+        assert_eq!(parts[1].0, "b.com");
+        assert_eq!(page_ids(&parts[1].1), vec!["page_1"]);
+    }
+
+    #[test]
+    fn test_by_time_window() {
+        let parts = split::by_time_window(&log(), Duration::from_secs(1)).unwrap();
+        let keys: Vec<&str> = parts.iter().map(|part| part.0.as_str()).collect();
+        assert_eq!(keys, vec!["2009-04-16T12:07:20.000Z", "2009-04-16T12:07:21.000Z",
+                              "2009-04-16T12:07:25.000Z"]);
+        assert_eq!(urls(&parts[0].1), vec!["http://a.com/1", "http://a.com/4"]);
+        assert_eq!(urls(&parts[1].1), vec!["http://b.com/2"]);
+        assert_eq!(urls(&parts[2].1), vec!["http://a.com/3"]);
+        assert!(parts[2].1.pages.as_ref().unwrap().is_empty());
+
+        let parts = split::by_time_window(&log(), Duration::MAX).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].1.entries.len(), 4);
+        assert!(split::by_time_window(&log(), Duration::from_secs(0)).is_err());
+        assert!(split::by_time_window(&log(), Duration::from_micros(999)).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+//! Summary statistics of a log.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use url::Url;
+
+use CacheState;
+use Entry;
+use Log;
+
+/// Totals and breakdowns over the entries of a log, as returned by `Log::stats`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stats {
+    /// The number of entries.
+    pub requests: usize,
+
+    /// Bytes transferred in both directions: the sum of the known `headers_size` and
+    /// `body_size` of every request and response.
+    pub bytes: u64,
+
+    /// Requests and bytes by URL host.
+    pub by_host: BTreeMap<String, Breakdown>,
+
+    /// Requests and bytes by response MIME type, without parameters.
+    pub by_mime_type: BTreeMap<String, Breakdown>,
+
+    /// Requests and bytes by response status class, such as "2xx". Requests without a status
+    /// (e.g. aborted ones) are counted under "none".
+    pub by_status_class: BTreeMap<String, Breakdown>,
+
+    /// How often the browser cache held the response.
+    pub cache: CacheStats,
+
+    /// What compressing response content saved.
+    pub compression: CompressionStats,
+
+    /// The distribution of the time spent in each phase.
+    pub timings: TimingStats,
+}
+
+/// The share of a group of entries.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Breakdown {
+    pub requests: usize,
+    pub bytes: u64,
+}
+
+/// Cache lookups, counted from `Cache.before_request`. Entries whose cache state is unknown are
+/// not counted.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct CacheStats {
+    /// Entries with a cache entry before the request.
+    pub hits: usize,
+
+    /// Entries with a known cache state before the request.
+    pub lookups: usize,
+}
+
+impl CacheStats {
+    /// The share of lookups that found a cache entry, if there were any lookups.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        if self.lookups == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / self.lookups as f64)
+        }
+    }
+}
+
+/// Compression savings, counted over entries whose `Content.compression` is known.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct CompressionStats {
+    /// The uncompressed size of their content.
+    pub size: u64,
+
+    /// The bytes compression saved.
+    pub saved: u64,
+}
+
+impl CompressionStats {
+    /// The share of the uncompressed size that compression saved, if anything was compressed.
+    pub fn ratio(&self) -> Option<f64> {
+        if self.size == 0 {
+            None
+        } else {
+            Some(self.saved as f64 / self.size as f64)
+        }
+    }
+}
+
+/// Percentiles of each timing phase. Phases that did not apply to an entry are left out of its
+/// distribution; a phase that never applied has no percentiles.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct TimingStats {
+    pub blocked: Option<Percentiles>,
+    pub dns: Option<Percentiles>,
+    pub connect: Option<Percentiles>,
+    pub ssl: Option<Percentiles>,
+    pub send: Option<Percentiles>,
+    pub wait: Option<Percentiles>,
+    pub receive: Option<Percentiles>,
+
+    /// The whole request, as given by `Timing::total`.
+    pub total: Option<Percentiles>,
+}
+
+impl TimingStats {
+    /// Each phase with its name, in the order of the HAR spec, followed by the total.
+    pub fn phases(&self) -> Vec<(&'static str, Option<&Percentiles>)> {
+        vec![
+            ("blocked", self.blocked.as_ref()),
+            ("dns", self.dns.as_ref()),
+            ("connect", self.connect.as_ref()),
+            ("ssl", self.ssl.as_ref()),
+            ("send", self.send.as_ref()),
+            ("wait", self.wait.as_ref()),
+            ("receive", self.receive.as_ref()),
+            ("total", self.total.as_ref()),
+        ]
+    }
+}
+
+/// Nearest-rank percentiles of a set of durations, in milliseconds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Percentiles {
+    pub count: usize,
+    pub min: u32,
+    pub median: u32,
+    pub p90: u32,
+    pub p95: u32,
+    pub p99: u32,
+    pub max: u32,
+}
+
+impl Percentiles {
+    /// Computes the percentiles of `samples`, or `None` if there are none.
+    pub fn of(mut samples: Vec<u32>) -> Option<Percentiles> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let rank = |percent: usize| samples[(percent * samples.len()).div_ceil(100) - 1];
+        Some(Percentiles {
+            count: samples.len(),
+            min: samples[0],
+            median: rank(50),
+            p90: rank(90),
+            p95: rank(95),
+            p99: rank(99),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
+
+impl Log {
+    /// Computes totals and breakdowns over the entries of this log.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            requests: self.entries.len(),
+            bytes: 0,
+            by_host: BTreeMap::new(),
+            by_mime_type: BTreeMap::new(),
+            by_status_class: BTreeMap::new(),
+            cache: CacheStats::default(),
+            compression: CompressionStats::default(),
+            timings: TimingStats::default(),
+        };
+        let mut phases: Vec<Vec<u32>> = vec![Vec::new(); 8];
+
+        for entry in &self.entries {
+            let bytes = transferred(entry);
+            stats.bytes += bytes;
+            for (groups, key) in [(&mut stats.by_host, host(entry)),
+                                  (&mut stats.by_mime_type, mime_type(entry)),
+                                  (&mut stats.by_status_class, status_class(entry))] {
+                let breakdown = groups.entry(key).or_default();
+                breakdown.requests += 1;
+                breakdown.bytes += bytes;
+            }
+
+            match entry.cache.before_request {
+                CacheState::Present(_) => {
+                    stats.cache.hits += 1;
+                    stats.cache.lookups += 1;
+                }
+                CacheState::Absent => stats.cache.lookups += 1,
+                CacheState::Unknown => {}
+            }
+
+            let content = &entry.response.content;
+            if let Some(saved) = content.compression {
+                stats.compression.size += non_negative(content.size);
+                stats.compression.saved += non_negative(saved);
+            }
+
+            let timings = &entry.timings;
+            let samples = [timings.blocked.millis(), timings.dns.millis(),
+                           timings.connect.millis(), timings.ssl.millis(), Some(timings.send),
+                           Some(timings.wait), Some(timings.receive), Some(timings.total())];
+            for (phase, sample) in phases.iter_mut().zip(samples.iter()) {
+                phase.extend(*sample);
+            }
+        }
+
+        let mut phases = phases.into_iter().map(Percentiles::of);
+        let mut next = || phases.next().unwrap();
+        stats.timings = TimingStats {
+            blocked: next(),
+            dns: next(),
+            connect: next(),
+            ssl: next(),
+            send: next(),
+            wait: next(),
+            receive: next(),
+            total: next(),
+        };
+        stats
+    }
+}
+
+/// Sizes of -1 or less mean "unknown".
+fn non_negative(size: i32) -> u64 {
+    if size > 0 { size as u64 } else { 0 }
+}
+
+fn transferred(entry: &Entry) -> u64 {
+    let request = &entry.request;
+    let response = &entry.response;
+    [request.headers_size, request.body_size, response.headers_size, response.body_size]
+        .iter()
+        .map(|size| non_negative(size.unwrap_or(0)))
+        .sum()
+}
+
+fn host(entry: &Entry) -> String {
+    Url::parse(&entry.request.url).ok()
+        .and_then(|url| url.host_str().map(|host| host.to_ascii_lowercase()))
+        .unwrap_or_else(|| "(no host)".to_string())
+}
+
+fn mime_type(entry: &Entry) -> String {
+    let mime_type = entry.response.content.mime_type.split(';').next().unwrap_or("").trim();
+    if mime_type.is_empty() {
+        "(none)".to_string()
+    } else {
+        mime_type.to_ascii_lowercase()
+    }
+}
+
+fn status_class(entry: &Entry) -> String {
+    match entry.response.status {
+        status @ 100..=999 => format!("{}xx", status / 100),
+        _ => "none".to_string(),
+    }
+}
+
+fn write_breakdowns(f: &mut fmt::Formatter,
+                    label: &str,
+                    groups: &BTreeMap<String, Breakdown>) -> fmt::Result {
+    writeln!(f, "by {}:", label)?;
+    for (key, breakdown) in groups {
+        writeln!(f, "    {}: {} requests, {} bytes", key, breakdown.requests, breakdown.bytes)?;
+    }
+    Ok(())
+}
+
+fn percent(ratio: f64) -> String {
+    format!("{:.1}%", ratio * 100.0)
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "requests: {}", self.requests)?;
+        writeln!(f, "transferred: {} bytes", self.bytes)?;
+        match self.cache.hit_ratio() {
+            Some(ratio) => writeln!(f, "cache hits: {} of {} ({})",
+                                    self.cache.hits, self.cache.lookups, percent(ratio))?,
+            None => writeln!(f, "cache hits: unknown")?,
+        }
+        match self.compression.ratio() {
+            Some(ratio) => writeln!(f, "compression saved: {} of {} bytes ({})",
+                                    self.compression.saved, self.compression.size,
+                                    percent(ratio))?,
+            None => writeln!(f, "compression saved: unknown")?,
+        }
+        write_breakdowns(f, "host", &self.by_host)?;
+        write_breakdowns(f, "MIME type", &self.by_mime_type)?;
+        write_breakdowns(f, "status", &self.by_status_class)?;
+        writeln!(f, "timings (ms):")?;
+        for (phase, percentiles) in self.timings.phases() {
+            if let Some(p) = percentiles {
+                writeln!(f, "    {}: min {}, median {}, p90 {}, p95 {}, p99 {}, max {}",
+                         phase, p.min, p.median, p.p90, p.p95, p.p99, p.max)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use stats::{Breakdown, Percentiles};
+    use Cache;
+    use CacheEntry;
+    use CacheState::{self, Absent, Present, Unknown};
+    use Content;
+    use Entry;
+    use Log;
+    use OptionalTiming::{NotApplicable, TimedContent};
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(url: &str, status: i32, mime_type: &str, sizes: (i32, i32), compression: Option<i32>,
+             cache: CacheState, wait: u32) -> Entry {
+        Entry::new(
+            None,
+            "2009-04-16T12:07:20.000Z".to_string(),
+            Request::new("GET".to_string(), url.to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                         Vec::new(), Vec::new(), None, Some(100), Some(0), None),
+            Response::new(status, "".to_string(), "HTTP/1.1".to_string(), Vec::new(), Vec::new(),
+                          Content::new(sizes.1, compression, mime_type.to_string(), None, None,
+                                       None),
+                          "".to_string(), Some(sizes.0), Some(sizes.1), None),
+            Cache::new(cache, Unknown, None),
+            Timing::new(NotApplicable, TimedContent(5), NotApplicable, 1, wait, 2, NotApplicable,
+                        None),
+            None,
+            None,
+            None
+        )
+    }
+
+    #[test]
+    fn test_percentiles() {
+        assert_eq!(Percentiles::of(Vec::new()), None);
+        let p = Percentiles::of((1..101).rev().collect()).unwrap();
+        assert_eq!((p.count, p.min, p.median, p.p90, p.p95, p.p99, p.max),
+                   (100, 1, 50, 90, 95, 99, 100));
+        let p = Percentiles::of(vec![7]).unwrap();
+        assert_eq!((p.min, p.median, p.p99, p.max), (7, 7, 7, 7));
+    }
+
+    #[test]
+    fn test_stats() {
+        let cached = Present(CacheEntry::new(None, "".to_string(), "".to_string(), 1, None));
+        let mut log = Log::new(None, None);
+        log.add_entry(entry("http://example.com/", 200, "text/html; charset=utf-8", (200, 1000),
+                            Some(600), Absent, 10));
+        log.add_entry(entry("http://example.com/a.png", 304, "image/png", (150, -1), None,
+                            cached, 20));
+        log.add_entry(entry("http://cdn.example.com/a.js", 0, "", (-1, -1), None, Unknown, 30));
+        let stats = log.stats();
+
+        assert_eq!(stats.requests, 3);
+        assert_eq!(stats.bytes, 1300 + 250 + 100);
+        assert_eq!(stats.by_host["example.com"], Breakdown { requests: 2, bytes: 1550 });
+        assert_eq!(stats.by_host["cdn.example.com"], Breakdown { requests: 1, bytes: 100 });
+        assert_eq!(stats.by_mime_type["text/html"], Breakdown { requests: 1, bytes: 1300 });
+        assert_eq!(stats.by_mime_type["(none)"].requests, 1);
+        assert_eq!(stats.by_status_class.keys().collect::<Vec<_>>(), vec!["2xx", "3xx", "none"]);
+
+        assert_eq!((stats.cache.hits, stats.cache.lookups), (1, 2));
+        assert_eq!(stats.cache.hit_ratio(), Some(0.5));
+        assert_eq!((stats.compression.size, stats.compression.saved), (1000, 600));
+        assert_eq!(stats.compression.ratio(), Some(0.6));
+
+        assert_eq!(stats.timings.blocked, None);
+        assert_eq!(stats.timings.dns.unwrap().max, 5);
+        assert_eq!(stats.timings.wait.unwrap().median, 20);
+        assert_eq!(stats.timings.total.unwrap().max, 38);
+
+        let report = stats.to_string();
+        assert!(report.contains("cache hits: 1 of 2 (50.0%)\n"));
+        assert!(report.contains("    example.com: 2 requests, 1550 bytes\n"));
+        assert!(report.contains("    wait: min 10, median 20, p90 30, p95 30, p99 30, max 30\n"));
+    }
+
+    #[test]
+    fn test_stats_of_empty_log() {
+        let stats = Log::new(None, None).stats();
+        assert_eq!(stats.requests, 0);
+        assert_eq!(stats.cache.hit_ratio(), None);
+        assert_eq!(stats.compression.ratio(), None);
+        assert_eq!(stats.timings.total, None);
+    }
+}
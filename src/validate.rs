@@ -0,0 +1,217 @@
+//! Checking a log against the HAR 1.2 spec.
+//!
+//! Deserializing a log only checks its shape. `validate` also checks what the spec says about
+//! the values: dates are ISO 8601, page references resolve, the query string matches the URL,
+//! sizes are -1 or more, and so on.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use chrono::DateTime;
+use url::Url;
+
+use Entry;
+use Log;
+
+/// Something in a log that does not follow the spec.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Problem {
+    /// Where the problem is, as a JSON path from the log object, e.g. `$.entries[3].request.url`.
+    pub path: String,
+
+    /// What is wrong.
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Checks `log`, returning every problem found in document order.
+pub fn validate(log: &Log) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    {
+        let mut problem = |path: String, message: String| {
+            problems.push(Problem { path, message });
+        };
+
+        if log.version != "1.1" && log.version != "1.2" {
+            problem("$.version".to_string(), format!("unknown HAR version \"{}\"", log.version));
+        }
+
+        let mut ids = HashSet::new();
+        for (i, page) in log.pages.iter().flatten().enumerate() {
+            let path = format!("$.pages[{}]", i);
+            if page.started().is_none() {
+                problem(format!("{}.startedDateTime", path),
+                        format!("\"{}\" is not an ISO 8601 date", page.started_date_time));
+            }
+            if !ids.insert(page.id.as_str()) {
+                problem(format!("{}.id", path), format!("duplicate page id \"{}\"", page.id));
+            }
+        }
+
+        for (i, entry) in log.entries.iter().enumerate() {
+            check_entry(&format!("$.entries[{}]", i), entry, &ids, &mut problem);
+        }
+    }
+    problems
+}
+
+fn check_entry<F>(path: &str, entry: &Entry, pages: &HashSet<&str>, problem: &mut F)
+    where F: FnMut(String, String)
+{
+    if let Some(ref pageref) = entry.pageref {
+        if !pages.contains(pageref.as_str()) {
+            problem(format!("{}.pageref", path), format!("no page has the id \"{}\"", pageref));
+        }
+    }
+    if DateTime::parse_from_rfc3339(&entry.started_date_time).is_err() {
+        problem(format!("{}.startedDateTime", path),
+                format!("\"{}\" is not an ISO 8601 date", entry.started_date_time));
+    }
+
+    let request = &entry.request;
+    if request.method.is_empty() {
+        problem(format!("{}.request.method", path), "empty method".to_string());
+    }
+    if let Err(err) = Url::parse(&request.url) {
+        problem(format!("{}.request.url", path), format!("invalid URL: {}", err));
+    } else if !request.query_matches_url() {
+        problem(format!("{}.request.queryString", path),
+                "does not match the query of the URL".to_string());
+    }
+    for &(field, size) in &[("headersSize", request.headers_size),
+                            ("bodySize", request.body_size)] {
+        check_size(&format!("{}.request.{}", path, field), size, problem);
+    }
+
+    let response = &entry.response;
+    if response.status != 0 && !(100..=599).contains(&response.status) {
+        problem(format!("{}.response.status", path),
+                format!("{} is not an HTTP status", response.status));
+    }
+    for &(field, size) in &[("headersSize", response.headers_size),
+                            ("bodySize", response.body_size)] {
+        check_size(&format!("{}.response.{}", path, field), size, problem);
+    }
+    let content = &response.content;
+    if content.size < 0 {
+        problem(format!("{}.response.content.size", path),
+                format!("negative size {}", content.size));
+    }
+    if let Err(err) = content.body_bytes() {
+        problem(format!("{}.response.content.text", path), err.to_string());
+    }
+
+    let timings = &entry.timings;
+    if let (Some(ssl), Some(connect)) = (timings.ssl.millis(), timings.connect.millis()) {
+        if ssl > connect {
+            problem(format!("{}.timings.ssl", path),
+                    format!("ssl time {}ms exceeds the connect time {}ms it is part of",
+                            ssl, connect));
+        }
+    }
+}
+
+fn check_size<F: FnMut(String, String)>(path: &str, size: Option<i32>, problem: &mut F) {
+    if let Some(size) = size {
+        if size < -1 {
+            problem(path.to_string(), format!("size {} is neither -1 nor a byte count", size));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use validate::{self, Problem};
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Log;
+    use OptionalTiming::{NotApplicable, TimedContent};
+    use Page;
+    use PageTimings;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(pageref: &str, started: &str, url: &str, status: i32, content: Content,
+             timings: Timing) -> Entry {
+        Entry::new(
+            Some(pageref.to_string()),
+            started.to_string(),
+            Request::new("GET".to_string(), url.to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                         Vec::new(), Vec::new(), None, Some(-1), Some(0), None),
+            Response::new(status, "".to_string(), "HTTP/1.1".to_string(), Vec::new(), Vec::new(),
+                          content, "".to_string(), Some(-5), None, None),
+            Cache::new(Absent, Absent, None),
+            timings,
+            None,
+            None,
+            None
+        )
+    }
+
+    fn page(id: &str, started: &str) -> Page {
+        Page::new(started.to_string(), id.to_string(), "".to_string(),
+                  PageTimings::new(NotApplicable, NotApplicable, None), None)
+    }
+
+    #[test]
+    fn test_valid_log() {
+        let mut log = Log::new(None, None);
+        log.add_page(page("page_0", "2009-04-16T12:07:20.000Z"));
+        let mut valid = entry("page_0", "2009-04-16T12:07:20.000+02:00",
+                              "http://example.com/?q=a+b", 200,
+                              Content::new(0, None, "".to_string(), None, None, None),
+                              Timing::new(NotApplicable, NotApplicable, TimedContent(5), 1, 2, 3,
+                                          TimedContent(5), None));
+        valid.response.headers_size = None;
+        valid.request.query_from_url().unwrap();
+        log.add_entry(valid);
+        assert_eq!(validate::validate(&log), Vec::new());
+    }
+
+    #[test]
+    fn test_invalid_log() {
+        let mut log = Log::new(None, None);
+        log.add_page(page("page_0", "yesterday"));
+        log.add_page(page("page_0", "2009-04-16T12:07:20.000Z"));
+        log.add_entry(entry("page_1", "2009-04-16", "/relative", 42,
+                            Content::new(-2, None, "".to_string(), Some("!".to_string()),
+                                         Some("base64".to_string()), None),
+                            Timing::new(NotApplicable, NotApplicable, TimedContent(5), 1, 2, 3,
+                                        TimedContent(6), None)));
+        let mut stale_query = entry("page_0", "2009-04-16T12:07:20.000Z",
+                                    "http://example.com/?a=1", 200,
+                                    Content::new(0, None, "".to_string(), None, None, None),
+                                    Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 2,
+                                                3, NotApplicable, None));
+        stale_query.response.headers_size = None;
+        log.add_entry(stale_query);
+
+        let problems: Vec<String> = validate::validate(&log).iter()
+            .map(Problem::to_string)
+            .collect();
+        assert_eq!(problems, vec![
+            "$.pages[0].startedDateTime: \"yesterday\" is not an ISO 8601 date",
+            "$.pages[1].id: duplicate page id \"page_0\"",
+            "$.entries[0].pageref: no page has the id \"page_1\"",
+            "$.entries[0].startedDateTime: \"2009-04-16\" is not an ISO 8601 date",
+            "$.entries[0].request.url: invalid URL: relative URL without a base",
+            "$.entries[0].response.status: 42 is not an HTTP status",
+            "$.entries[0].response.headersSize: size -5 is neither -1 nor a byte count",
+            "$.entries[0].response.content.size: negative size -2",
+            "$.entries[0].response.content.text: invalid base64 content: \
+             Invalid symbol 33, offset 0.",
+            "$.entries[0].timings.ssl: ssl time 6ms exceeds the connect time 5ms it is part of",
+            "$.entries[1].request.queryString: does not match the query of the URL",
+        ]);
+    }
+}
@@ -0,0 +1,453 @@
+//! Waterfall charts of request timelines.
+//!
+//! A `Waterfall` lays out one bar per entry, starting at the entry's `started_date_time` and
+//! split into the phases of its `Timing`, along with markers for the `DOMContentLoaded` and
+//! `load` events of its pages. It renders as plain text for terminals or as a standalone SVG:
+//!
+//! ```
+//! use har::Log;
+//! use har::waterfall::Waterfall;
+//!
+//! let log = Log::new(None, None);
+//! print!("{}", Waterfall::from_log(&log).to_ascii(80));
+//! ```
+
+use std::fmt::Write;
+
+use chrono::{DateTime, FixedOffset};
+
+use Entry;
+use Log;
+use Page;
+
+/// Width of the request labels in ASCII charts, in characters.
+const ASCII_LABEL_WIDTH: usize = 40;
+
+/// Layout of SVG charts, in pixels.
+const SVG_LABEL_WIDTH: usize = 320;
+const SVG_CHART_WIDTH: usize = 680;
+const SVG_ROW_HEIGHT: usize = 20;
+
+/// A phase of a request, as in `Timing`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Blocked,
+    Dns,
+    Connect,
+    Ssl,
+    Send,
+    Wait,
+    Receive,
+}
+
+impl Phase {
+    /// Every phase, in the order they happen.
+    pub const ALL: [Phase; 7] = [Phase::Blocked, Phase::Dns, Phase::Connect, Phase::Ssl,
+                                 Phase::Send, Phase::Wait, Phase::Receive];
+
+    /// The name of the phase as in `Timing`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Blocked => "blocked",
+            Phase::Dns => "dns",
+            Phase::Connect => "connect",
+            Phase::Ssl => "ssl",
+            Phase::Send => "send",
+            Phase::Wait => "wait",
+            Phase::Receive => "receive",
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Phase::Blocked => '-',
+            Phase::Dns => 'd',
+            Phase::Connect => 'c',
+            Phase::Ssl => 's',
+            Phase::Send => '>',
+            Phase::Wait => '.',
+            Phase::Receive => '#',
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Phase::Blocked => "#c8c8c8",
+            Phase::Dns => "#1f9e89",
+            Phase::Connect => "#f28e2b",
+            Phase::Ssl => "#b07aa1",
+            Phase::Send => "#4e79a7",
+            Phase::Wait => "#59a14f",
+            Phase::Receive => "#2f6fd0",
+        }
+    }
+}
+
+/// A page event.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event {
+    ContentLoad,
+    Load,
+}
+
+impl Event {
+    /// The name of the DOM event.
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::ContentLoad => "DOMContentLoaded",
+            Event::Load => "load",
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Event::ContentLoad => 'C',
+            Event::Load => 'L',
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Event::ContentLoad => "#1d4ed8",
+            Event::Load => "#dc2626",
+        }
+    }
+}
+
+/// One bar of a waterfall.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bar<'a> {
+    pub entry: &'a Entry,
+
+    /// When the request started, in milliseconds after the start of the chart.
+    pub start: f64,
+
+    /// The phases that took time, in order, with their durations in milliseconds. SSL time is
+    /// taken out of the connect phase, which the HAR spec counts it in.
+    pub phases: Vec<(Phase, f64)>,
+}
+
+impl<'a> Bar<'a> {
+    /// When the request finished, in milliseconds after the start of the chart.
+    pub fn end(&self) -> f64 {
+        self.start + self.phases.iter().map(|&(_, duration)| duration).sum::<f64>()
+    }
+}
+
+/// A page event, in milliseconds after the start of the chart.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Marker {
+    pub event: Event,
+    pub time: f64,
+}
+
+/// The bars and markers of a waterfall chart.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Waterfall<'a> {
+    pub bars: Vec<Bar<'a>>,
+    pub markers: Vec<Marker>,
+}
+
+impl<'a> Waterfall<'a> {
+    /// Lays out every entry of `log` with the events of every page. Entries and pages whose
+    /// start cannot be parsed are left out.
+    pub fn from_log(log: &'a Log) -> Waterfall<'a> {
+        let pages: Vec<&Page> = log.pages.iter().flatten().collect();
+        Waterfall::layout(log.entries.iter().collect(), pages)
+    }
+
+    /// Lays out the entries of the page with id `id`, or returns `None` if there is no such page.
+    pub fn from_page(log: &'a Log, id: &str) -> Option<Waterfall<'a>> {
+        let page = log.pages.iter().flatten().find(|page| page.id == id)?;
+        let entries = log.entries.iter()
+            .filter(|entry| entry.pageref.as_ref().is_some_and(|pageref| pageref == id))
+            .collect();
+        Some(Waterfall::layout(entries, vec![page]))
+    }
+
+    /// Lays out `entries` alone, without page events.
+    pub fn from_entries<I: IntoIterator<Item = &'a Entry>>(entries: I) -> Waterfall<'a> {
+        Waterfall::layout(entries.into_iter().collect(), Vec::new())
+    }
+
+    fn layout(entries: Vec<&'a Entry>, pages: Vec<&Page>) -> Waterfall<'a> {
+        let entries: Vec<(&Entry, DateTime<FixedOffset>)> = entries.into_iter()
+            .filter_map(|entry| entry.started().map(|started| (entry, started)))
+            .collect();
+        let pages: Vec<(&Page, DateTime<FixedOffset>)> = pages.into_iter()
+            .filter_map(|page| page.started().map(|started| (page, started)))
+            .collect();
+        let origin = match entries.iter().map(|&(_, started)| started)
+            .chain(pages.iter().map(|&(_, started)| started))
+            .min() {
+            Some(origin) => origin,
+            None => return Waterfall { bars: Vec::new(), markers: Vec::new() },
+        };
+        let offset = |time: DateTime<FixedOffset>| {
+            (time - origin).num_microseconds().unwrap_or(0) as f64 / 1000.0
+        };
+
+        let bars = entries.into_iter()
+            .map(|(entry, started)| {
+                let timings = &entry.timings;
+                let ssl = timings.ssl.millis().unwrap_or(0);
+                let connect = timings.connect.millis().unwrap_or(0).saturating_sub(ssl);
+                let durations = [timings.blocked.millis().unwrap_or(0),
+                                 timings.dns.millis().unwrap_or(0), connect, ssl, timings.send,
+                                 timings.wait, timings.receive];
+                let phases = Phase::ALL.iter()
+                    .zip(durations.iter())
+                    .filter(|&(_, &duration)| duration > 0)
+                    .map(|(&phase, &duration)| (phase, f64::from(duration)))
+                    .collect();
+                Bar { entry, start: offset(started), phases }
+            })
+            .collect();
+
+        let mut markers = Vec::new();
+        for (page, started) in pages {
+            let timings = &page.page_timings;
+            for &(event, time) in &[(Event::ContentLoad, &timings.on_content_load),
+                                    (Event::Load, &timings.on_load)] {
+                if let Some(time) = time.millis() {
+                    markers.push(Marker { event, time: offset(started) + f64::from(time) });
+                }
+            }
+        }
+        Waterfall { bars, markers }
+    }
+
+    /// When the last request finished or the last event fired, in milliseconds after the start.
+    pub fn duration(&self) -> f64 {
+        self.bars.iter()
+            .map(Bar::end)
+            .chain(self.markers.iter().map(|marker| marker.time))
+            .fold(0.0, f64::max)
+    }
+
+    /// Renders the chart as text, with bars `width` characters wide at most. Each phase is drawn
+    /// with its own character and the events are marked on the bottom line.
+    pub fn to_ascii(&self, width: usize) -> String {
+        let width = width.max(1);
+        let duration = self.duration();
+        let column = |time: f64| if duration > 0.0 {
+            ((time / duration * width as f64).round() as usize).min(width)
+        } else {
+            0
+        };
+
+        let mut out = String::new();
+        for bar in &self.bars {
+            let mut line = vec![' '; width];
+            let mut time = bar.start;
+            for &(phase, length) in &bar.phases {
+                for cell in &mut line[column(time)..column(time + length)] {
+                    *cell = phase.symbol();
+                }
+                time += length;
+            }
+            // Keep short requests visible.
+            if line.iter().all(|&c| c == ' ') {
+                let symbol = bar.phases.last().map_or('#', |&(phase, _)| phase.symbol());
+                line[column(bar.start).min(width - 1)] = symbol;
+            }
+            let line: String = line.into_iter().collect();
+            writeln!(out, "{} |{}| {:.0}ms",
+                     label(bar.entry), line, bar.end() - bar.start).unwrap();
+        }
+
+        if !self.markers.is_empty() {
+            let mut line = vec![' '; width];
+            for marker in &self.markers {
+                line[column(marker.time).min(width - 1)] = marker.event.symbol();
+            }
+            let line: String = line.into_iter().collect();
+            writeln!(out, "{:width$} |{}|", "", line, width = ASCII_LABEL_WIDTH).unwrap();
+        }
+
+        let legend: Vec<String> = Phase::ALL.iter()
+            .map(|phase| format!("{} {}", phase.symbol(), phase.name()))
+            .chain(self.markers.iter()
+                   .map(|marker| format!("{} {} ({:.0}ms)",
+                                         marker.event.symbol(), marker.event.name(),
+                                         marker.time)))
+            .collect();
+        writeln!(out, "{}", legend.join("  ")).unwrap();
+        out
+    }
+
+    /// Renders the chart as a standalone SVG document. Hovering over a phase shows its duration.
+    pub fn to_svg(&self) -> String {
+        let duration = self.duration();
+        let scale = if duration > 0.0 { SVG_CHART_WIDTH as f64 / duration } else { 0.0 };
+        let chart_height = self.bars.len() * SVG_ROW_HEIGHT;
+        let width = SVG_LABEL_WIDTH + SVG_CHART_WIDTH + 10;
+        let height = chart_height + 2 * SVG_ROW_HEIGHT;
+
+        let mut out = String::new();
+        writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                       font-family=\"sans-serif\" font-size=\"11\">", width, height).unwrap();
+        for (row, bar) in self.bars.iter().enumerate() {
+            let y = row * SVG_ROW_HEIGHT;
+            writeln!(out, "<text x=\"4\" y=\"{}\">{}</text>",
+                     y + 14, escape(&label(bar.entry))).unwrap();
+            let mut time = bar.start;
+            for &(phase, length) in &bar.phases {
+                writeln!(out, "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" \
+                               fill=\"{}\"><title>{}: {:.0}ms</title></rect>",
+                         SVG_LABEL_WIDTH as f64 + time * scale, y + 4, length * scale,
+                         SVG_ROW_HEIGHT - 8, phase.color(), phase.name(), length).unwrap();
+                time += length;
+            }
+        }
+        for marker in &self.markers {
+            let x = SVG_LABEL_WIDTH as f64 + marker.time * scale;
+            writeln!(out, "<line x1=\"{:.1}\" y1=\"0\" x2=\"{:.1}\" y2=\"{}\" stroke=\"{}\">\
+                           <title>{}: {:.0}ms</title></line>",
+                     x, x, chart_height, marker.event.color(), marker.event.name(),
+                     marker.time).unwrap();
+        }
+
+        let mut x = 4;
+        let y = chart_height + SVG_ROW_HEIGHT;
+        for phase in Phase::ALL.iter() {
+            writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"10\" height=\"10\" fill=\"{}\"/>\
+                           <text x=\"{}\" y=\"{}\">{}</text>",
+                     x, y, phase.color(), x + 14, y + 9, phase.name()).unwrap();
+            x += 80;
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+/// The method and URL of `entry`, cut or padded to `ASCII_LABEL_WIDTH` characters.
+fn label(entry: &Entry) -> String {
+    let label = format!("{} {}", entry.request.method, entry.request.url);
+    if label.chars().count() > ASCII_LABEL_WIDTH {
+        let cut: String = label.chars().take(ASCII_LABEL_WIDTH - 3).collect();
+        format!("{}...", cut)
+    } else {
+        format!("{:width$}", label, width = ASCII_LABEL_WIDTH)
+    }
+}
+
+/// Escapes `text` for use in XML text and attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+
+#[cfg(test)]
+mod test {
+
+    use waterfall::{self, Event, Marker, Phase, Waterfall};
+    use Cache;
+    use CacheState::Absent;
+    use Content;
+    use Entry;
+    use Log;
+    use OptionalTiming::{NotApplicable, TimedContent};
+    use Page;
+    use PageTimings;
+    use Request;
+    use Response;
+    use Timing;
+
+    fn entry(pageref: &str, url: &str, started: &str, timings: Timing) -> Entry {
+        Entry::new(
+            Some(pageref.to_string()),
+            started.to_string(),
+            Request::new("GET".to_string(), url.to_string(), "HTTP/1.1".to_string(), Vec::new(),
+                         Vec::new(), Vec::new(), None, None, None, None),
+            Response::new(200, "OK".to_string(), "HTTP/1.1".to_string(), Vec::new(), Vec::new(),
+                          Content::new(0, None, "text/plain".to_string(), None, None, None),
+                          "".to_string(), None, None, None),
+            Cache::new(Absent, Absent, None),
+            timings,
+            None,
+            None,
+            None
+        )
+    }
+
+    fn page(id: &str, started: &str, on_content_load: u32, on_load: u32) -> Page {
+        Page::new(started.to_string(), id.to_string(), "".to_string(),
+                  PageTimings::new(TimedContent(on_content_load), TimedContent(on_load), None),
+                  None)
+    }
+
+    fn log() -> Log {
+        let mut log = Log::new(None, None);
+        log.add_page(page("page_0", "2009-04-16T12:07:20.000Z", 60, 100));
+        log.add_entry(entry("page_0", "http://a.example/", "2009-04-16T12:07:20.000Z",
+                            Timing::new(TimedContent(10), TimedContent(10), TimedContent(30), 0,
+                                        20, 10, TimedContent(10), None)));
+        log.add_entry(entry("page_0", "http://a.example/app.js", "2009-04-16T12:07:20.050Z",
+                            Timing::new(NotApplicable, NotApplicable, NotApplicable, 0, 10, 40,
+                                        NotApplicable, None)));
+        log.add_entry(entry("page_1", "http://b.example/", "not a date",
+                            Timing::new(NotApplicable, NotApplicable, NotApplicable, 1, 1, 1,
+                                        NotApplicable, None)));
+        log
+    }
+
+    #[test]
+    fn test_layout() {
+        let log = log();
+        let waterfall = Waterfall::from_log(&log);
+        assert_eq!(waterfall.bars.len(), 2);
+        assert_eq!(waterfall.bars[0].start, 0.0);
+        assert_eq!(waterfall.bars[0].phases, vec![(Phase::Blocked, 10.0), (Phase::Dns, 10.0),
+                                                  (Phase::Connect, 20.0), (Phase::Ssl, 10.0),
+                                                  (Phase::Wait, 20.0), (Phase::Receive, 10.0)]);
+        assert_eq!(waterfall.bars[0].end(), 80.0);
+        assert_eq!(waterfall.bars[1].start, 50.0);
+        assert_eq!(waterfall.markers, vec![Marker { event: Event::ContentLoad, time: 60.0 },
+                                           Marker { event: Event::Load, time: 100.0 }]);
+        assert_eq!(waterfall.duration(), 100.0);
+
+        assert!(Waterfall::from_page(&log, "page_1").is_none());
+        assert_eq!(Waterfall::from_page(&log, "page_0").unwrap().bars.len(), 2);
+    }
+
+    #[test]
+    fn test_ascii() {
+        let log = log();
+        let ascii = Waterfall::from_log(&log).to_ascii(20);
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], format!("{:40} |--ddccccss....##    | 80ms", "GET http://a.example/"));
+        assert_eq!(lines[1], format!("{:40} |          ..########| 50ms",
+                                     "GET http://a.example/app.js"));
+        assert_eq!(lines[2], format!("{:40} |            C      L|", ""));
+        assert!(lines[3].starts_with("- blocked  d dns  c connect  s ssl"));
+        assert!(lines[3].ends_with("C DOMContentLoaded (60ms)  L load (100ms)"));
+
+        assert_eq!(Waterfall::from_log(&Log::new(None, None)).to_ascii(20).lines().count(), 1);
+    }
+
+    #[test]
+    fn test_svg() {
+        let log = log();
+        let svg = Waterfall::from_log(&log).to_svg();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert!(svg.contains("<rect x=\"320.0\" y=\"4\" width=\"68.0\" height=\"12\" \
+                              fill=\"#c8c8c8\"><title>blocked: 10ms</title></rect>"));
+        assert!(svg.contains("<line x1=\"1000.0\" y1=\"0\" x2=\"1000.0\" y2=\"40\" \
+                              stroke=\"#dc2626\"><title>load: 100ms</title></line>"));
+        assert_eq!(waterfall::escape("a<b & \"c\""), "a&lt;b &amp; &quot;c&quot;");
+    }
+}